@@ -0,0 +1,808 @@
+use crate::app::{App, AppResult, Host, HostState, LoadAverage, HOST_STATES};
+use crate::config::AlertConfig;
+use crate::error::{Error, ErrorClass};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Time the condition of an alert must stay false before the alert clears, unless configured.
+const DEFAULT_CLEAR: Duration = Duration::from_secs(30);
+
+/// Values rules can compare, along with their description.
+pub const VARIABLES: [(&str, &str); 11] = [
+    ("load1", "load average over 1 minute"),
+    ("load5", "load average over 5 minutes"),
+    ("load15", "load average over 15 minutes"),
+    ("running", "processes running"),
+    ("processes", "processes in total"),
+    ("ncpus", "number of processors"),
+    ("mem_used", "memory in use, in percent"),
+    ("mem_total", "total memory, in GiB"),
+    ("rtt", "time taken by the last sample, in milliseconds"),
+    ("handshake", "time taken to connect, in milliseconds"),
+    ("age", "seconds since the host last reported"),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(&'static str),
+}
+
+/// Splits a rule into tokens. Numbers directly followed by letters, like `60s`, give a number
+/// token followed by an identifier.
+fn tokenize(text: &str) -> AppResult<Vec<Token>> {
+    const SYMBOLS: [&str; 12] = [
+        ">=", "<=", "==", "!=", ">", "<", "+", "-", "*", "/", "(", ")",
+    ];
+
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() || c == '.' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..length].parse().map_err(|_| {
+                Error::Parse(format!("Invalid number in rule: {}", &rest[..length]))
+            })?;
+            tokens.push(Token::Number(number));
+            length
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..length].to_string()));
+            length
+        } else {
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| rest.starts_with(symbol))
+                .ok_or_else(|| Error::Parse(format!("Unexpected '{}' in rule: {}", c, text)))?;
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Arithmetic over the values of a host.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    Variable(String),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+}
+
+impl Expr {
+    /// Value of the expression for the host, `None` if a value it uses is not known.
+    fn eval(&self, host: &Host) -> Option<f64> {
+        match self {
+            Expr::Number(number) => Some(*number),
+            Expr::Variable(name) => variable(name, host),
+            Expr::Binary(left, operator, right) => {
+                let (left, right) = (left.eval(host)?, right.eval(host)?);
+                match *operator {
+                    "+" => Some(left + right),
+                    "-" => Some(left - right),
+                    "*" => Some(left * right),
+                    _ => Some(left / right).filter(|value| value.is_finite()),
+                }
+            }
+        }
+    }
+}
+
+fn variable(name: &str, host: &Host) -> Option<f64> {
+    let load = || match &host.state {
+        HostState::Up(load) => load.parse::<LoadAverage>().ok(),
+        _ => None,
+    };
+    let memory = || match host.state {
+        HostState::Up(_) => host.memory,
+        _ => None,
+    };
+
+    match name {
+        "load1" => load().map(|load| load.one),
+        "load5" => load().map(|load| load.five),
+        "load15" => load().map(|load| load.fifteen),
+        "running" => load().map(|load| load.running as f64),
+        "processes" => load().map(|load| load.total as f64),
+        "ncpus" => host.cpus().map(f64::from),
+        "mem_used" => memory()
+            .filter(|(total, _)| *total > 0)
+            .map(|(total, available)| {
+                100.0 * total.saturating_sub(available) as f64 / total as f64
+            }),
+        "mem_total" => memory().map(|(total, _)| total as f64 / (1024.0 * 1024.0)),
+        "rtt" => host.latency.back().map(|rtt| rtt.as_secs_f64() * 1000.0),
+        "handshake" => host
+            .handshake
+            .map(|handshake| handshake.as_secs_f64() * 1000.0),
+        "age" => Some(host.updated.elapsed().as_secs_f64()),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Condition {
+    Compare(Expr, &'static str, Expr),
+    /// `state == <name>`, or `!=` when negated.
+    State {
+        name: String,
+        negated: bool,
+    },
+    /// `error == <class>`, or `!=` when negated.
+    Error {
+        class: ErrorClass,
+        negated: bool,
+    },
+}
+
+impl Condition {
+    fn holds(&self, app: &App, host: &Host) -> bool {
+        match self {
+            Condition::Compare(left, operator, right) => {
+                let (Some(left), Some(right)) = (left.eval(host), right.eval(host)) else {
+                    return false;
+                };
+                match *operator {
+                    ">" => left > right,
+                    ">=" => left >= right,
+                    "<" => left < right,
+                    "<=" => left <= right,
+                    "==" => left == right,
+                    _ => left != right,
+                }
+            }
+            Condition::State { name, negated } => (app.state_name(host) == name) != *negated,
+            Condition::Error { class, negated } => {
+                (host.state.error().map(Error::class) == Some(*class)) != *negated
+            }
+        }
+    }
+}
+
+/// Recursive descent parser for rules:
+///
+/// ```text
+/// rule      := condition [ "for" duration ]
+/// condition := "state" ("==" | "!=") name | "error" ("==" | "!=") class | expr comparison expr
+/// expr      := term { ("+" | "-") term }
+/// term      := factor { ("*" | "/") factor }
+/// factor    := number | variable | "(" expr ")"
+/// ```
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> AppResult<Self> {
+        Ok(Self {
+            text,
+            tokens: tokenize(text)?,
+            position: 0,
+        })
+    }
+
+    fn error(&self, expected: &str) -> Error {
+        let found = match self.tokens.get(self.position) {
+            Some(Token::Number(number)) => number.to_string(),
+            Some(Token::Ident(ident)) => ident.clone(),
+            Some(Token::Symbol(symbol)) => symbol.to_string(),
+            None => String::from("end of rule"),
+        };
+        Error::Parse(format!(
+            "Expected {}, found {} in rule: {}",
+            expected, found, self.text
+        ))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn symbol(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                let symbol = *symbol;
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn ident(&mut self, expected: &str) -> AppResult<String> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.position += 1;
+                Ok(ident)
+            }
+            _ => Err(self.error(expected)),
+        }
+    }
+
+    fn rule(&mut self) -> AppResult<(Condition, Duration)> {
+        let condition = self.condition()?;
+        let duration = match self.peek() {
+            Some(Token::Ident(ident)) if ident == "for" => {
+                self.position += 1;
+                self.duration()?
+            }
+            _ => Duration::ZERO,
+        };
+
+        match self.peek() {
+            None => Ok((condition, duration)),
+            Some(_) => Err(self.error("end of rule")),
+        }
+    }
+
+    fn condition(&mut self) -> AppResult<Condition> {
+        let keyword = match self.peek() {
+            Some(Token::Ident(ident)) if ident == "state" || ident == "error" => ident.clone(),
+            _ => {
+                let left = self.expr()?;
+                let operator = self
+                    .symbol(&[">=", "<=", "==", "!=", ">", "<"])
+                    .ok_or_else(|| self.error("a comparison"))?;
+                return Ok(Condition::Compare(left, operator, self.expr()?));
+            }
+        };
+        self.position += 1;
+
+        let negated = match self.symbol(&["==", "!="]) {
+            Some(operator) => operator == "!=",
+            None => return Err(self.error("== or !=")),
+        };
+
+        match keyword.as_str() {
+            "state" => {
+                let name = self.ident("a state")?;
+                match HOST_STATES.contains(&name.as_str()) {
+                    true => Ok(Condition::State { name, negated }),
+                    false => Err(Error::Parse(format!(
+                        "Unknown state {} in rule: {}, expected one of {}",
+                        name,
+                        self.text,
+                        HOST_STATES.join(", ")
+                    ))),
+                }
+            }
+            _ => {
+                // Class names may contain dashes, which are tokenized apart
+                let mut name = self.ident("an error class")?;
+                while self.symbol(&["-"]).is_some() {
+                    name = format!("{}-{}", name, self.ident("an error class")?);
+                }
+                match ErrorClass::from_name(&name) {
+                    Some(class) => Ok(Condition::Error { class, negated }),
+                    None => Err(Error::Parse(format!(
+                        "Unknown error class {} in rule: {}",
+                        name, self.text
+                    ))),
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self) -> AppResult<Expr> {
+        let mut expr = self.term()?;
+        while let Some(operator) = self.symbol(&["+", "-"]) {
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> AppResult<Expr> {
+        let mut expr = self.factor()?;
+        while let Some(operator) = self.symbol(&["*", "/"]) {
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> AppResult<Expr> {
+        if self.symbol(&["("]).is_some() {
+            let expr = self.expr()?;
+            self.symbol(&[")"]).ok_or_else(|| self.error(")"))?;
+            return Ok(expr);
+        }
+
+        match self.peek().cloned() {
+            Some(Token::Number(number)) => {
+                self.position += 1;
+                Ok(Expr::Number(number))
+            }
+            Some(Token::Ident(name)) if VARIABLES.iter().any(|(known, _)| *known == name) => {
+                self.position += 1;
+                Ok(Expr::Variable(name))
+            }
+            Some(Token::Ident(name)) => Err(Error::Parse(format!(
+                "Unknown value {} in rule: {}, expected one of {}",
+                name,
+                self.text,
+                VARIABLES.map(|(name, _)| name).join(", ")
+            ))),
+            _ => Err(self.error("a number or a value")),
+        }
+    }
+
+    fn duration(&mut self) -> AppResult<Duration> {
+        let Some(Token::Number(number)) = self.next() else {
+            self.position -= 1;
+            return Err(self.error("a duration"));
+        };
+        let unit = match self.peek() {
+            Some(Token::Ident(unit)) => {
+                let unit = unit.clone();
+                self.position += 1;
+                unit
+            }
+            _ => String::from("s"),
+        };
+
+        let seconds = match unit.as_str() {
+            "ms" => number / 1000.0,
+            "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => {
+                self.position -= 1;
+                return Err(self.error("a unit among ms, s, m and h"));
+            }
+        };
+        Duration::try_from_secs_f64(seconds).map_err(|_| self.error("a valid duration"))
+    }
+}
+
+/// Parses a duration such as `90s`, `2m` or `1.5h`. Numbers without a unit are seconds.
+pub fn parse_duration(text: &str) -> AppResult<Duration> {
+    let mut parser = Parser::new(text)?;
+    let duration = parser.duration()?;
+    match parser.peek() {
+        None => Ok(duration),
+        Some(_) => Err(parser.error("end of duration")),
+    }
+}
+
+/// A condition watched on every host.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub name: String,
+    /// The rule as written in the configuration.
+    pub text: String,
+    condition: Condition,
+    /// Time the condition must hold before the alert fires.
+    pub duration: Duration,
+    /// Time the condition must stay false before the alert clears.
+    pub clear: Duration,
+    /// Shell command run when the alert fires or clears on a host.
+    pub hook: Option<String>,
+}
+
+impl Rule {
+    pub fn parse(config: &AlertConfig) -> AppResult<Self> {
+        let (condition, duration) = Parser::new(&config.rule)?.rule()?;
+        let clear = config
+            .clear
+            .as_deref()
+            .map(parse_duration)
+            .transpose()?
+            .unwrap_or(DEFAULT_CLEAR);
+
+        Ok(Self {
+            name: config.name.clone().unwrap_or_else(|| config.rule.clone()),
+            text: config.rule.clone(),
+            condition,
+            duration,
+            clear,
+            hook: config.hook.clone(),
+        })
+    }
+}
+
+/// State of a rule on a host on which its condition held at some point.
+#[derive(Clone, Copy, Debug)]
+enum AlertState {
+    /// The condition holds since the given time, but not for long enough to fire.
+    Pending(Instant),
+    /// The alert fired at `since`. The condition does not hold anymore since `clearing`, if set.
+    Firing {
+        since: Instant,
+        clearing: Option<Instant>,
+    },
+}
+
+/// An alert firing or clearing on a host.
+#[derive(Clone, Debug)]
+pub struct Transition {
+    pub rule: usize,
+    pub host: String,
+    pub firing: bool,
+}
+
+/// Alert rules and their state on every host.
+///
+/// Alerts fire once their condition held for the duration of the rule, and clear once it stayed
+/// false for the clear delay of the rule, so that hosts flapping around a threshold only fire
+/// once.
+#[derive(Debug, Default)]
+pub struct Alerts {
+    pub rules: Vec<Rule>,
+    /// States by rule index and hostname.
+    states: HashMap<(usize, String), AlertState>,
+    /// Whether the alerts panel is shown.
+    pub visible: bool,
+    /// Number of lines scrolled in the alerts view.
+    pub scroll: u16,
+}
+
+impl Alerts {
+    pub fn from_config(alerts: &[AlertConfig]) -> AppResult<Self> {
+        Ok(Self {
+            rules: alerts.iter().map(Rule::parse).collect::<AppResult<_>>()?,
+            ..Self::default()
+        })
+    }
+
+    /// Checks the rules on every host of `app`, returning the alerts that fired or cleared.
+    ///
+    /// Alerts firing on hosts that are not part of `app` anymore are cleared.
+    pub fn evaluate(&mut self, app: &App) -> Vec<Transition> {
+        let now = Instant::now();
+        let mut transitions = vec![];
+        self.states.retain(|(rule, host), state| {
+            if app.hosts.contains_key(host) {
+                return true;
+            }
+            if let AlertState::Firing { .. } = state {
+                transitions.push(Transition {
+                    rule: *rule,
+                    host: host.clone(),
+                    firing: false,
+                });
+            }
+            false
+        });
+        transitions.sort_by(|a, b| (a.rule, &a.host).cmp(&(b.rule, &b.host)));
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            for (name, host) in app.hosts.iter() {
+                let key = (index, name.clone());
+                let holds = rule.condition.holds(app, host);
+                let state = match (self.states.get(&key).copied(), holds) {
+                    (None, false) => continue,
+                    (None, true) => AlertState::Pending(now),
+                    (Some(AlertState::Pending(_)), false) => {
+                        self.states.remove(&key);
+                        continue;
+                    }
+                    (Some(AlertState::Pending(start)), true) => AlertState::Pending(start),
+                    (Some(AlertState::Firing { since, .. }), true) => AlertState::Firing {
+                        since,
+                        clearing: None,
+                    },
+                    (Some(AlertState::Firing { since, clearing }), false) => {
+                        let clearing = clearing.unwrap_or(now);
+                        if now.duration_since(clearing) >= rule.clear {
+                            self.states.remove(&key);
+                            transitions.push(Transition {
+                                rule: index,
+                                host: name.clone(),
+                                firing: false,
+                            });
+                            continue;
+                        }
+                        AlertState::Firing {
+                            since,
+                            clearing: Some(clearing),
+                        }
+                    }
+                };
+
+                let state = match state {
+                    AlertState::Pending(start) if now.duration_since(start) >= rule.duration => {
+                        transitions.push(Transition {
+                            rule: index,
+                            host: name.clone(),
+                            firing: true,
+                        });
+                        AlertState::Firing {
+                            since: now,
+                            clearing: None,
+                        }
+                    }
+                    state => state,
+                };
+                self.states.insert(key, state);
+            }
+        }
+
+        transitions
+    }
+
+    /// Forgets the state of every alert, without clearing them.
+    pub fn reset(&mut self) {
+        self.states.clear();
+    }
+
+    /// Number of alerts firing on the host.
+    pub fn firing_count(&self, host: &str) -> usize {
+        self.states
+            .iter()
+            .filter(|((_, name), state)| name == host && matches!(state, AlertState::Firing { .. }))
+            .count()
+    }
+
+    /// Sorted hosts the condition of the rule holds on, but not for long enough to fire yet.
+    pub fn pending_hosts(&self, rule: usize) -> Vec<&str> {
+        let mut hosts: Vec<&str> = self
+            .states
+            .iter()
+            .filter(|((index, _), state)| *index == rule && matches!(state, AlertState::Pending(_)))
+            .map(|((_, host), _)| host.as_str())
+            .collect();
+        hosts.sort_unstable();
+        hosts
+    }
+
+    /// Rules firing on at least one host, along with the sorted hosts they fire on and the time
+    /// they first fired.
+    pub fn firing_rules(&self) -> Vec<(&Rule, Vec<&str>, Instant)> {
+        let mut firing = vec![];
+        for (index, rule) in self.rules.iter().enumerate() {
+            let mut hosts = vec![];
+            let mut first = None;
+            for ((_, host), state) in self.states.iter().filter(|((i, _), _)| *i == index) {
+                if let AlertState::Firing { since, .. } = state {
+                    hosts.push(host.as_str());
+                    first = Some(first.map_or(*since, |first: Instant| first.min(*since)));
+                }
+            }
+
+            if let Some(first) = first {
+                hosts.sort_unstable();
+                firing.push((rule, hosts, first));
+            }
+        }
+        firing
+    }
+}
+
+/// Runs the hook of an alert in the background, describing the alert in its environment.
+pub fn run_hook(command: &str, env: Vec<(&'static str, String)>) {
+    let mut child = tokio::process::Command::new("sh");
+    child
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+
+    let command = command.to_string();
+    tokio::spawn(async move {
+        match child.status().await {
+            Ok(status) if !status.success() => log::warn!("Hook `{}` failed: {}", command, status),
+            Ok(_) => (),
+            Err(e) => log::warn!("Could not run hook `{}`: {}", command, e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(rule: &str) -> AppResult<(Condition, Duration)> {
+        Parser::new(rule)?.rule()
+    }
+
+    fn alerts(rule: &str, clear: &str) -> Alerts {
+        Alerts::from_config(&[AlertConfig {
+            name: None,
+            rule: rule.to_string(),
+            hook: None,
+            clear: Some(clear.to_string()),
+        }])
+        .unwrap()
+    }
+
+    fn firing(transitions: Vec<Transition>) -> Vec<bool> {
+        transitions
+            .into_iter()
+            .map(|transition| transition.firing)
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_rules() {
+        assert_eq!(
+            tokenize("load1>=2.5 for 60s").unwrap(),
+            [
+                Token::Ident("load1".into()),
+                Token::Symbol(">="),
+                Token::Number(2.5),
+                Token::Ident("for".into()),
+                Token::Number(60.0),
+                Token::Ident("s".into()),
+            ]
+        );
+        assert!(tokenize("load1 > 1.2.3").is_err());
+        assert!(tokenize("load1 % 2").is_err());
+    }
+
+    #[test]
+    fn parses_expressions_with_precedence() {
+        let (condition, duration) = rule("load1 / ncpus > 1 + 2 * 3").unwrap();
+        let variable = |name: &str| Box::new(Expr::Variable(name.into()));
+        let number = |number| Box::new(Expr::Number(number));
+        assert_eq!(
+            condition,
+            Condition::Compare(
+                Expr::Binary(variable("load1"), "/", variable("ncpus")),
+                ">",
+                Expr::Binary(
+                    number(1.0),
+                    "+",
+                    Box::new(Expr::Binary(number(2.0), "*", number(3.0)))
+                ),
+            )
+        );
+        assert_eq!(duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for text in [
+            "",
+            "load1",
+            "load1 >",
+            "load1 > 1 2",
+            "(load1 > 1",
+            "(load1 + 1 > 2",
+            "load > 1",
+            "state = down",
+            "state == sleeping",
+            "error == disk",
+            "load1 > 1 for",
+            "load1 > 1 for 5d",
+            "load1 > 1 for 5s later",
+        ] {
+            assert!(
+                matches!(rule(text), Err(Error::Parse(_))),
+                "{} should not parse",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        let duration = |text| rule(text).unwrap().1;
+        assert_eq!(duration("load1 > 1 for 90"), Duration::from_secs(90));
+        assert_eq!(duration("load1 > 1 for 500ms"), Duration::from_millis(500));
+        assert_eq!(duration("load1 > 1 for 2m"), Duration::from_secs(120));
+        assert_eq!(duration("load1 > 1 for 1.5h"), Duration::from_secs(5400));
+
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert!(parse_duration("30 s s").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+
+    #[test]
+    fn parses_clear_delays() {
+        let config = |clear: Option<&str>| AlertConfig {
+            name: None,
+            rule: String::from("load1 > 1"),
+            hook: None,
+            clear: clear.map(String::from),
+        };
+        assert_eq!(Rule::parse(&config(None)).unwrap().clear, DEFAULT_CLEAR);
+        assert_eq!(
+            Rule::parse(&config(Some("2m"))).unwrap().clear,
+            Duration::from_secs(120)
+        );
+        assert!(Rule::parse(&config(Some("soon"))).is_err());
+    }
+
+    #[test]
+    fn parses_state_and_error_conditions() {
+        assert_eq!(
+            rule("state != up").unwrap().0,
+            Condition::State {
+                name: "up".into(),
+                negated: true
+            }
+        );
+        assert_eq!(
+            rule("error == host-key for 1m").unwrap(),
+            (
+                Condition::Error {
+                    class: ErrorClass::HostKey,
+                    negated: false
+                },
+                Duration::from_secs(60)
+            )
+        );
+    }
+
+    #[test]
+    fn evaluates_state_and_error_conditions() {
+        let mut app = App::new();
+        app.add_host("n1", "n1");
+        app.add_host("n2", "n2");
+        app.set_host_connected("n1", Duration::from_millis(5));
+        app.set_host_error("n2", Error::Timeout(Duration::from_secs(1)));
+
+        let holds = |text: &str, name: &str| rule(text).unwrap().0.holds(&app, &app.hosts[name]);
+        assert!(holds("state == up", "n1"));
+        assert!(!holds("state == up", "n2"));
+        assert!(holds("state != up", "n2"));
+        assert!(holds("error == timeout", "n2"));
+        assert!(!holds("error == timeout", "n1"));
+        assert!(holds("error != auth", "n2"));
+        assert!(holds("handshake < 10", "n1"));
+        // Unknown values never hold
+        assert!(!holds("load1 >= 0", "n2"));
+    }
+
+    #[test]
+    fn fires_and_clears_with_hysteresis() {
+        let delay = Duration::from_millis(50);
+        let mut alerts = alerts("load1 > 1 for 50ms", "50ms");
+        let mut app = App::new();
+        app.add_host("n1", "n1");
+        let busy = |app: &mut App| app.set_host_status("n1", "4.00 1.00 1.00 1/1 1");
+        let idle = |app: &mut App| app.set_host_status("n1", "0.50 1.00 1.00 1/1 1");
+
+        // Pending until the condition held for long enough
+        busy(&mut app);
+        assert!(alerts.evaluate(&app).is_empty());
+        assert_eq!(alerts.pending_hosts(0), ["n1"]);
+        std::thread::sleep(delay);
+        assert_eq!(firing(alerts.evaluate(&app)), [true]);
+        assert_eq!(alerts.firing_count("n1"), 1);
+
+        // Flapping back above the threshold before the clear delay keeps it firing
+        idle(&mut app);
+        assert!(alerts.evaluate(&app).is_empty());
+        busy(&mut app);
+        std::thread::sleep(delay);
+        assert!(alerts.evaluate(&app).is_empty());
+        assert_eq!(alerts.firing_count("n1"), 1);
+
+        // Clears once the condition stayed false for the clear delay
+        idle(&mut app);
+        assert!(alerts.evaluate(&app).is_empty());
+        std::thread::sleep(delay);
+        assert_eq!(firing(alerts.evaluate(&app)), [false]);
+        assert_eq!(alerts.firing_count("n1"), 0);
+    }
+
+    #[test]
+    fn pending_alerts_clear_silently() {
+        let mut alerts = alerts("load1 > 1 for 1h", "0s");
+        let mut app = App::new();
+        app.add_host("n1", "n1");
+        app.set_host_status("n1", "4.00 1.00 1.00 1/1 1");
+        assert!(alerts.evaluate(&app).is_empty());
+        app.set_host_status("n1", "0.50 1.00 1.00 1/1 1");
+        assert!(alerts.evaluate(&app).is_empty());
+        assert!(alerts.pending_hosts(0).is_empty());
+    }
+}
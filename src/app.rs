@@ -1,14 +1,348 @@
-use std::collections::HashMap;
-use std::error;
+use crate::alert::Alerts;
+use crate::command::{CommandRun, CommandStatus, OutputLine};
+use crate::drift::{Check, DriftRun};
+use crate::error::{Error, ErrorClass};
+use crate::history::{Entry, History};
+use crate::inventory::{Inventory, GROUP_FIELDS};
+use crate::keymap::Keymap;
+use crate::nodes;
+use crate::record::ReplayCommand;
+use crate::theme::Theme;
+use log::Level;
+use ratatui::{layout::Rect, widgets::TableState};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Longest time between the two clicks of a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
 
 /// Application result type.
-pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
+pub type AppResult<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum HostState {
+    /// Waiting for a connection slot.
+    Queued,
     Connecting,
+    Authenticating,
+    /// Connected, with the last load sampled, empty until the first sample.
     Up(String),
-    Down(String),
+    /// Connected, but sampling failed.
+    Degraded(Error),
+    /// Waiting for the given delay before reconnecting.
+    Backoff(Duration, Error),
+    /// Connection failed for good.
+    Down(Error),
+}
+
+impl HostState {
+    /// Error the host is in, if any.
+    pub fn error(&self) -> Option<&Error> {
+        match self {
+            HostState::Degraded(error) | HostState::Backoff(_, error) | HostState::Down(error) => {
+                Some(error)
+            }
+            _ => None,
+        }
+    }
+
+    /// Line logged when a host enters the state.
+    fn describe(&self) -> (Level, String) {
+        match self {
+            HostState::Queued => (Level::Info, String::from("Waiting for a connection slot")),
+            HostState::Connecting => (Level::Info, String::from("Connecting")),
+            HostState::Authenticating => (Level::Info, String::from("Authenticating")),
+            HostState::Up(_) => (Level::Info, String::from("Up")),
+            HostState::Degraded(error) => (Level::Warn, format!("Sampling failed: {}", error)),
+            HostState::Backoff(delay, error) => (
+                Level::Warn,
+                format!("{}, retrying in {}s", error, delay.as_secs()),
+            ),
+            HostState::Down(error) => (Level::Error, format!("{}, giving up", error)),
+        }
+    }
+}
+
+/// Names of the states of the hosts, as given by [`App::state_name`].
+pub const HOST_STATES: [&str; 8] = [
+    "queued",
+    "connecting",
+    "authenticating",
+    "up",
+    "stale",
+    "degraded",
+    "backoff",
+    "down",
+];
+
+/// Number of sample latencies kept per host.
+pub const LATENCY_HISTORY: usize = 60;
+
+/// A monitored host.
+#[derive(Debug)]
+pub struct Host {
+    pub state: HostState,
+    /// Nodeset expression the host was given as on the command line.
+    pub group: String,
+    /// Total and available memory in kB, when sampled.
+    pub memory: Option<(u64, u64)>,
+    /// Static facts, collected once connected.
+    pub inventory: Option<Inventory>,
+    /// Time taken by the last samples, oldest first.
+    pub latency: VecDeque<Duration>,
+    /// Time taken to establish the current session.
+    pub handshake: Option<Duration>,
+    /// Last time a load sample was received from the host.
+    pub updated: Instant,
+}
+
+impl Host {
+    pub fn new(group: &str) -> Self {
+        Self {
+            state: HostState::Connecting,
+            group: group.to_string(),
+            memory: None,
+            inventory: None,
+            latency: VecDeque::with_capacity(LATENCY_HISTORY),
+            handshake: None,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Number of processors, once the inventory is collected.
+    pub fn cpus(&self) -> Option<u32> {
+        self.inventory.as_ref()?.cpus
+    }
+
+    /// Value of a fact of the inventory, once collected.
+    pub fn fact(&self, field: &str) -> Option<String> {
+        self.inventory.as_ref()?.get(field)
+    }
+
+    /// Latest load average, while the host is up.
+    pub fn load(&self) -> Option<LoadAverage> {
+        match &self.state {
+            HostState::Up(content) => content.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Value the host is ranked by when sorting on a numerical column.
+    fn sort_value(&self, column: SortColumn) -> Option<f64> {
+        match column {
+            SortColumn::Host => None,
+            SortColumn::Load => self.load().map(|load| load.one),
+            SortColumn::LoadPerCpu => self.load()?.normalized(self.cpus()?),
+            SortColumn::Memory => self
+                .memory
+                .filter(|(total, _)| *total > 0)
+                .map(|(total, available)| total.saturating_sub(available) as f64 / total as f64),
+            SortColumn::Age => Some(self.updated.elapsed().as_secs_f64()),
+            SortColumn::Rtt => self.latency.back().map(Duration::as_secs_f64),
+            SortColumn::Handshake => self.handshake.as_ref().map(Duration::as_secs_f64),
+        }
+    }
+}
+
+/// Tabs of the interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum View {
+    /// Summary of the state of every host.
+    Overview,
+    /// Table of the hosts.
+    Hosts,
+    /// Lustre filesystems mounted on the hosts.
+    Lustre,
+    /// Every alert rule and the hosts it fires on.
+    Alerts,
+    /// Full screen log.
+    Log,
+}
+
+impl View {
+    /// Every view, in the order of their tabs.
+    pub const ALL: [View; 5] = [
+        View::Overview,
+        View::Hosts,
+        View::Lustre,
+        View::Alerts,
+        View::Log,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            View::Overview => "overview",
+            View::Hosts => "hosts",
+            View::Lustre => "lustre",
+            View::Alerts => "alerts",
+            View::Log => "log",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|view| *view == self)
+            .unwrap_or_default()
+    }
+
+    /// View of the next tab, or of the previous one, wrapping around.
+    pub fn cycle(self, forward: bool) -> Self {
+        let offset = match forward {
+            true => 1,
+            false => Self::ALL.len() - 1,
+        };
+        Self::ALL[(self.index() + offset) % Self::ALL.len()]
+    }
+}
+
+/// Columns of the host table the rows can be sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortColumn {
+    Host,
+    Load,
+    LoadPerCpu,
+    Memory,
+    Age,
+    Rtt,
+    Handshake,
+}
+
+impl SortColumn {
+    /// Every column, in the order they are cycled through.
+    pub const ALL: [SortColumn; 7] = [
+        SortColumn::Host,
+        SortColumn::Load,
+        SortColumn::LoadPerCpu,
+        SortColumn::Memory,
+        SortColumn::Age,
+        SortColumn::Rtt,
+        SortColumn::Handshake,
+    ];
+}
+
+/// Position of the widgets on screen during the last render, to find what the mouse points at.
+#[derive(Clone, Debug, Default)]
+pub struct Areas {
+    /// Titles of the tabs, with the view they switch to.
+    pub tabs: Vec<(Rect, View)>,
+    /// Host table, header included.
+    pub table: Rect,
+    /// Cells of the header of the host table, with the column they sort by.
+    pub header: Vec<(Rect, Option<SortColumn>)>,
+    pub log: Rect,
+    /// Content of the active tab.
+    pub view: Rect,
+    /// Results of a command or drift checks.
+    pub results: Rect,
+}
+
+/// Parsed contents of `/proc/loadavg`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+    pub running: u64,
+    pub total: u64,
+}
+
+impl LoadAverage {
+    /// 1 minute load average per processor, 1 meaning every processor is busy.
+    pub fn normalized(&self, cpus: u32) -> Option<f64> {
+        (cpus > 0).then(|| self.one / cpus as f64)
+    }
+}
+
+impl FromStr for LoadAverage {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Parse(format!("Invalid load average: {}", s.trim()));
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [one, five, fifteen, processes, ..] = fields[..] else {
+            return Err(invalid());
+        };
+        let (running, total) = processes.split_once('/').ok_or_else(invalid)?;
+
+        Ok(Self {
+            one: one.parse().map_err(|_| invalid())?,
+            five: five.parse().map_err(|_| invalid())?,
+            fifteen: fifteen.parse().map_err(|_| invalid())?,
+            running: running.parse().map_err(|_| invalid())?,
+            total: total.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Requests from the interface that need to be carried out by the main loop.
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Run a command on the given hosts.
+    Exec {
+        id: u64,
+        command: String,
+        hosts: Vec<String>,
+    },
+    /// Run the drift checks with the given commands on the given hosts.
+    Drift {
+        id: u64,
+        commands: Vec<String>,
+        hosts: Vec<String>,
+    },
+    /// Stop the running command or drift checks.
+    CancelExec,
+    /// Start monitoring the given hosts, along with their group.
+    AddHosts(Vec<(String, String)>),
+    /// Stop monitoring the given hosts and close their sessions.
+    RemoveHosts(Vec<String>),
+    /// Run the hook of an alert, with the given environment.
+    Hook {
+        command: String,
+        env: Vec<(&'static str, String)>,
+    },
+}
+
+/// What the text typed in the prompt is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptKind {
+    /// A command run on the marked hosts.
+    Command,
+    /// A nodeset of hosts to monitor.
+    AddHosts,
+    /// A nodeset of hosts to stop monitoring.
+    RemoveHosts,
+}
+
+impl PromptKind {
+    /// Text shown before the contents of the prompt.
+    pub fn label(self) -> &'static str {
+        match self {
+            PromptKind::Command => ":",
+            PromptKind::AddHosts => "add hosts: ",
+            PromptKind::RemoveHosts => "remove hosts (empty for the marked ones): ",
+        }
+    }
+}
+
+/// Contents of the prompt.
+#[derive(Debug)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub text: String,
+}
+
+/// State of a session replay.
+#[derive(Debug)]
+pub struct Replay {
+    /// Sender used to drive the replay task.
+    pub control: mpsc::UnboundedSender<ReplayCommand>,
+    pub position: Duration,
+    pub duration: Duration,
+    pub speed: f64,
+    pub paused: bool,
 }
 
 /// Application.
@@ -16,18 +350,95 @@ pub enum HostState {
 pub struct App {
     /// Is the application running?
     pub running: bool,
-    /// counter
-    pub counter: u8,
+    /// View of the active tab.
+    pub view: View,
+
+    pub hosts: HashMap<String, Host>,
+
+    /// Set when replaying a recorded session.
+    pub replay: Option<Replay>,
+
+    /// Age after which the data of a host that is up is considered stale.
+    pub stale_after: Duration,
 
-    pub hosts: HashMap<String, HostState>,
+    /// Selection in the host table.
+    pub table_state: TableState,
+    /// Column the rows are sorted by, and whether in descending order.
+    pub sort: (SortColumn, bool),
+    /// Row last clicked, and when, to detect double clicks.
+    last_click: Option<(usize, Instant)>,
+    /// Position of the widgets during the last render.
+    pub areas: Areas,
+    /// Show hosts down or backing off with the same error on a single row.
+    pub fold_errors: bool,
+    /// Only show the hosts failing with errors of this class.
+    pub error_filter: Option<ErrorClass>,
+    /// Show one row per value of this fact of the inventory, instead of one row per host.
+    pub group_by: Option<&'static str>,
+    /// Only show the hosts with this value of a fact, `None` standing for an unknown value.
+    pub fact_filter: Option<(&'static str, Option<String>)>,
+    /// Actions bound to the keys of the host table.
+    pub keymap: Keymap,
+    /// Colours of the interface.
+    pub theme: Theme,
+    /// Scroll of the help overlay, if shown.
+    pub help: Option<u16>,
+    /// Host whose details are shown in a popup.
+    pub popup: Option<String>,
+    /// Hosts removed at runtime whose tasks are still stopping, their events still queued are
+    /// ignored.
+    pub removed: HashSet<String>,
+    /// Hosts added back while the tasks of their removal were still stopping, along with their
+    /// group. Their new tasks are only started once the old ones stopped.
+    readded: HashMap<String, String>,
+    /// Hosts marked to receive the next command.
+    pub marked: HashSet<String>,
+    /// Contents of the prompt, if open.
+    pub prompt: Option<Prompt>,
+    /// Last command run and its results.
+    pub command: Option<CommandRun>,
+    /// Checks compared across hosts to detect configuration drift.
+    pub checks: Vec<Check>,
+    /// Last drift detection run and its results.
+    pub drift: Option<DriftRun>,
+    /// Log of the state changes of the hosts and of the messages of the application.
+    pub history: History,
+    /// Alert rules and the hosts they fire on.
+    pub alerts: Alerts,
+    /// Actions waiting to be processed by the main loop.
+    pub actions: Vec<Action>,
 }
 
 impl Default for App {
     fn default() -> Self {
         Self {
             running: true,
-            counter: 0,
+            view: View::Hosts,
             hosts: HashMap::new(),
+            replay: None,
+            stale_after: Duration::from_secs(3),
+            table_state: TableState::default(),
+            sort: (SortColumn::Host, false),
+            last_click: None,
+            areas: Areas::default(),
+            fold_errors: true,
+            error_filter: None,
+            group_by: None,
+            fact_filter: None,
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            help: None,
+            popup: None,
+            removed: HashSet::new(),
+            readded: HashMap::new(),
+            marked: HashSet::new(),
+            prompt: None,
+            command: None,
+            checks: Check::from_config(&[]),
+            drift: None,
+            history: History::default(),
+            alerts: Alerts::default(),
+            actions: vec![],
         }
     }
 }
@@ -39,46 +450,775 @@ impl App {
     }
 
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    pub fn tick(&mut self) {
+        self.check_alerts();
+    }
+
+    /// Evaluates the alert rules, logging the alerts that fired or cleared and running their
+    /// hooks. Hooks are not run when replaying a recording.
+    pub fn check_alerts(&mut self) {
+        let mut alerts = std::mem::take(&mut self.alerts);
+        let transitions = alerts.evaluate(self);
+
+        for transition in transitions {
+            let rule = &alerts.rules[transition.rule];
+            let (level, status) = match transition.firing {
+                true => (Level::Error, "firing"),
+                false => (Level::Info, "resolved"),
+            };
+            self.history.push(Entry::new(
+                level,
+                Some(&transition.host),
+                format!("Alert {} {}", rule.name, status),
+            ));
+
+            if let (Some(command), None) = (rule.hook.as_ref(), self.replay.as_ref()) {
+                self.actions.push(Action::Hook {
+                    command: command.clone(),
+                    env: vec![
+                        ("JBTOP_ALERT", rule.name.clone()),
+                        ("JBTOP_RULE", rule.text.clone()),
+                        ("JBTOP_HOST", transition.host),
+                        ("JBTOP_STATUS", status.to_string()),
+                    ],
+                });
+            }
+        }
+
+        self.alerts = alerts;
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.running = false;
     }
 
-    pub fn increment_counter(&mut self) {
-        if let Some(res) = self.counter.checked_add(1) {
-            self.counter = res;
+    /// Starts a replay driven through the given control channel.
+    pub fn start_replay(&mut self, control: mpsc::UnboundedSender<ReplayCommand>) {
+        self.replay = Some(Replay {
+            control,
+            position: Duration::ZERO,
+            duration: Duration::ZERO,
+            speed: 1.0,
+            paused: false,
+        });
+    }
+
+    /// Forwards a command to the replay task, if any.
+    pub fn control_replay(&mut self, command: ReplayCommand) {
+        if let Some(replay) = self.replay.as_ref() {
+            let _ = replay.control.send(command);
         }
     }
 
-    pub fn decrement_counter(&mut self) {
-        if let Some(res) = self.counter.checked_sub(1) {
-            self.counter = res;
+    pub fn set_replay_position(
+        &mut self,
+        position: Duration,
+        duration: Duration,
+        speed: f64,
+        paused: bool,
+    ) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.position = position;
+            replay.duration = duration;
+            replay.speed = speed;
+            replay.paused = paused;
         }
     }
 
-    pub fn set_host_connecting(&mut self, host: &str) {
-        let state = HostState::Connecting;
-        self.hosts
-            .entry(host.to_string())
-            .and_modify(|v| *v = state)
-            .or_insert(HostState::Connecting);
+    /// Forgets everything known about the hosts.
+    pub fn clear_hosts(&mut self) {
+        self.hosts.clear();
+        self.history.clear_hosts();
+        self.alerts.reset();
     }
 
-    pub fn set_host_status(&mut self, host: &str, load: &str) {
-        let state = HostState::Up(load.to_string());
+    /// Hostnames in the order they are displayed.
+    pub fn host_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.hosts.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Hosts of each row of the host table.
+    ///
+    /// When error folding is enabled, hosts that are down with the same error share a row. When
+    /// an error filter is set, only the hosts failing with errors of that class are listed. When
+    /// grouping by a fact, hosts sharing the same value share a row, largest groups first.
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let mut rows: Vec<Vec<String>> = vec![];
+        let mut folded = HashMap::<&Error, usize>::new();
+        let mut groups = HashMap::<Option<String>, usize>::new();
+        for host in self.host_names() {
+            let state = &self.hosts[&host].state;
+            if self.error_filter.is_some() && state.error().map(Error::class) != self.error_filter {
+                continue;
+            }
+            if let Some((field, value)) = self.fact_filter.as_ref() {
+                if self.hosts[&host].fact(field) != *value {
+                    continue;
+                }
+            }
+
+            if let Some(field) = self.group_by {
+                let value = self.hosts[&host].fact(field);
+                match groups.get(&value) {
+                    Some(&row) => rows[row].push(host),
+                    None => {
+                        groups.insert(value, rows.len());
+                        rows.push(vec![host]);
+                    }
+                }
+                continue;
+            }
+
+            match state {
+                HostState::Down(error) | HostState::Backoff(_, error) if self.fold_errors => {
+                    match folded.get(error) {
+                        Some(&row) => rows[row].push(host),
+                        None => {
+                            folded.insert(error, rows.len());
+                            rows.push(vec![host]);
+                        }
+                    }
+                }
+                _ => rows.push(vec![host]),
+            }
+        }
+
+        match self.sort {
+            _ if self.group_by.is_some() => {
+                rows.sort_by_key(|hosts| std::cmp::Reverse(hosts.len()))
+            }
+            (SortColumn::Host, false) => (),
+            (SortColumn::Host, true) => rows.reverse(),
+            (column, descending) => {
+                // Rows are in name order, so the sort being stable keeps ties sorted by name.
+                // Hosts without a value come last either way.
+                rows.sort_by(|a, b| {
+                    let a = self.hosts[&a[0]].sort_value(column);
+                    let b = self.hosts[&b[0]].sort_value(column);
+                    match (a, b) {
+                        (Some(a), Some(b)) if descending => b.total_cmp(&a),
+                        (Some(a), Some(b)) => a.total_cmp(&b),
+                        (a, b) => b.is_some().cmp(&a.is_some()),
+                    }
+                })
+            }
+        }
+        rows
+    }
+
+    /// Sorts the rows by the column, reversing the order if they already are. Numerical columns
+    /// are sorted in descending order first, to show the busiest hosts at the top.
+    pub fn sort_by(&mut self, column: SortColumn) {
+        self.sort = match self.sort {
+            (current, descending) if current == column => (column, !descending),
+            _ => (column, column != SortColumn::Host),
+        };
+    }
+
+    /// Sorts the rows by the next column, in the default order of that column.
+    pub fn cycle_sort(&mut self) {
+        let (current, _) = self.sort;
+        let next = SortColumn::ALL
+            .iter()
+            .cycle()
+            .skip_while(|column| **column != current)
+            .nth(1)
+            .copied()
+            .unwrap_or(SortColumn::Host);
+        self.sort_by(next);
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.sort.1 = !self.sort.1;
+    }
+
+    /// Selects the given row of the host table, opening the details of its hosts if it was
+    /// clicked twice in a row within [`DOUBLE_CLICK`].
+    pub fn click_row(&mut self, row: usize) {
+        if row >= self.rows().len() {
+            return;
+        }
+        self.table_state.select(Some(row));
+
+        match self.last_click.take() {
+            Some((last, time)) if last == row && time.elapsed() < DOUBLE_CLICK => self.open_popup(),
+            _ => self.last_click = Some((row, Instant::now())),
+        }
+    }
+
+    /// Hostnames of the highlighted row.
+    pub fn selected_hosts(&self) -> Vec<String> {
+        self.table_state
+            .selected()
+            .and_then(|index| self.rows().into_iter().nth(index))
+            .unwrap_or_default()
+    }
+
+    pub fn select_next(&mut self) {
+        let last = self.rows().len().saturating_sub(1);
+        let next = self.table_state.selected().map_or(0, |i| (i + 1).min(last));
+        self.table_state.select(Some(next));
+    }
+
+    pub fn select_previous(&mut self) {
+        let previous = self
+            .table_state
+            .selected()
+            .map_or(0, |i| i.saturating_sub(1));
+        self.table_state.select(Some(previous));
+    }
+
+    pub fn select_first(&mut self) {
+        self.table_state.select(Some(0));
+    }
+
+    pub fn select_last(&mut self) {
+        let last = self.rows().len().saturating_sub(1);
+        self.table_state.select(Some(last));
+    }
+
+    /// Marks or unmarks the hosts of the highlighted row.
+    pub fn toggle_mark(&mut self) {
+        let hosts = self.selected_hosts();
+        if hosts.iter().all(|host| self.marked.contains(host)) {
+            hosts.iter().for_each(|host| {
+                self.marked.remove(host);
+            });
+        } else {
+            self.marked.extend(hosts);
+        }
+    }
+
+    /// Toggles the folding of hosts down with identical errors.
+    pub fn toggle_fold_errors(&mut self) {
+        self.fold_errors = !self.fold_errors;
+    }
+
+    /// Classes of the errors hosts are currently failing with.
+    pub fn error_classes(&self) -> BTreeSet<ErrorClass> {
         self.hosts
-            .entry(host.to_string())
-            .and_modify(|v| *v = state)
-            .or_insert(HostState::Up(load.to_string()));
+            .values()
+            .filter_map(|host| host.state.error().map(Error::class))
+            .collect()
+    }
+
+    /// Moves the error filter to the next class of error currently seen, then back to showing
+    /// every host.
+    pub fn cycle_error_filter(&mut self) {
+        let classes = self.error_classes();
+        self.error_filter = match self.error_filter {
+            None => classes.first().copied(),
+            Some(current) => classes.into_iter().find(|class| *class > current),
+        };
+        self.table_state.select(Some(0));
+    }
+
+    pub fn toggle_alerts(&mut self) {
+        self.alerts.visible = !self.alerts.visible;
+    }
+
+    pub fn toggle_log(&mut self) {
+        self.history.visible = !self.history.visible;
     }
 
-    pub fn set_host_error(&mut self, host: &str, error: &str) {
-        let state = HostState::Down(error.to_string());
+    /// Restricts the log to the hosts of the highlighted row, or shows every entry again.
+    pub fn toggle_log_hosts(&mut self) {
+        self.history.scroll = 0;
+        if self.history.hosts.take().is_none() {
+            self.history.hosts = Some(self.selected_hosts().into_iter().collect());
+            self.history.visible = true;
+        }
+    }
+
+    /// Groups the hosts by the next fact of the inventory, then back to one row per host.
+    pub fn cycle_group_by(&mut self) {
+        self.group_by = match self.group_by {
+            None => Some(GROUP_FIELDS[0]),
+            Some(current) => GROUP_FIELDS
+                .iter()
+                .skip_while(|field| **field != current)
+                .nth(1)
+                .copied(),
+        };
+        self.table_state.select(Some(0));
+    }
+
+    /// Only shows the hosts sharing the value of the highlighted group, and goes back to one row
+    /// per host.
+    pub fn filter_selected_group(&mut self) {
+        let (Some(field), Some(host)) = (self.group_by, self.selected_hosts().into_iter().next())
+        else {
+            return;
+        };
+        self.fact_filter = Some((field, self.hosts[&host].fact(field)));
+        self.group_by = None;
+        self.table_state.select(Some(0));
+    }
+
+    pub fn clear_fact_filter(&mut self) {
+        self.fact_filter = None;
+    }
+
+    /// Shows the details of the first host of the highlighted row.
+    pub fn open_popup(&mut self) {
+        self.popup = self.selected_hosts().into_iter().next();
+    }
+
+    pub fn close_popup(&mut self) {
+        self.popup = None;
+    }
+
+    /// Only shows the highlighted group when grouping by a fact, shows the details of the
+    /// highlighted host otherwise.
+    pub fn open_selected(&mut self) {
+        match self.group_by {
+            Some(_) => self.filter_selected_group(),
+            None => self.open_popup(),
+        }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.help = match self.help {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    /// Hands the pending actions over to the caller.
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    pub fn open_prompt(&mut self, kind: PromptKind) {
+        if kind != PromptKind::Command && self.replay.is_some() {
+            self.history.push(Entry::new(
+                Level::Warn,
+                None,
+                String::from("Hosts cannot be added or removed during a replay"),
+            ));
+            return;
+        }
+        self.prompt = Some(Prompt {
+            kind,
+            text: String::new(),
+        });
+    }
+
+    pub fn close_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// Runs the command typed in the prompt, or adds or removes the hosts it lists.
+    pub fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        let text = prompt.text.trim();
+        match prompt.kind {
+            PromptKind::Command => self.run_command(text),
+            PromptKind::AddHosts => match nodes::expand(text) {
+                Ok(hosts) => self.add_hosts(hosts),
+                Err(error) => self
+                    .history
+                    .push(Entry::new(Level::Error, None, error.to_string())),
+            },
+            PromptKind::RemoveHosts if text.is_empty() => {
+                let hosts = self.removal_targets();
+                self.remove_hosts(hosts);
+            }
+            PromptKind::RemoveHosts => match nodes::expand(text) {
+                Ok(hosts) => self.remove_hosts(hosts.into_iter().map(|(host, _)| host).collect()),
+                Err(error) => self
+                    .history
+                    .push(Entry::new(Level::Error, None, error.to_string())),
+            },
+        }
+    }
+
+    /// Runs the command on the marked hosts, or on every host if none is marked.
+    fn run_command(&mut self, command: &str) {
+        if command.is_empty() {
+            return;
+        }
+
+        let hosts = self.target_hosts();
+        let id = self.command.as_ref().map_or(0, |run| run.id + 1);
+        if self.command.is_some() || self.drift.take().is_some() {
+            self.actions.push(Action::CancelExec);
+        }
+        self.command = Some(CommandRun::new(id, command, &hosts));
+        self.actions.push(Action::Exec {
+            id,
+            command: command.to_string(),
+            hosts,
+        });
+    }
+
+    /// Hosts commands are run on: the marked hosts, or every host if none is marked.
+    fn target_hosts(&self) -> Vec<String> {
+        self.host_names()
+            .into_iter()
+            .filter(|host| self.marked.is_empty() || self.marked.contains(host))
+            .collect()
+    }
+
+    /// Hosts removed when no nodeset is given: the marked hosts, the hosts listed when a filter
+    /// is set, or the hosts of the highlighted row.
+    fn removal_targets(&self) -> Vec<String> {
+        if !self.marked.is_empty() {
+            return self
+                .host_names()
+                .into_iter()
+                .filter(|host| self.marked.contains(host))
+                .collect();
+        }
+        if self.error_filter.is_some() || self.fact_filter.is_some() {
+            return self.rows().into_iter().flatten().collect();
+        }
+        self.selected_hosts()
+    }
+
+    /// Starts monitoring the hosts not monitored yet.
+    pub fn add_hosts(&mut self, hosts: Vec<(String, String)>) {
+        let hosts: Vec<(String, String)> = hosts
+            .into_iter()
+            .filter(|(host, _)| !self.hosts.contains_key(host))
+            .collect();
+        if hosts.is_empty() {
+            return;
+        }
+
+        let names: Vec<&str> = hosts.iter().map(|(host, _)| host.as_str()).collect();
+        self.history.push(Entry::new(
+            Level::Info,
+            None,
+            format!("Adding {} ({})", nodes::fold(&names), names.len()),
+        ));
+        let (readded, hosts): (Vec<_>, Vec<_>) = hosts
+            .into_iter()
+            .partition(|(host, _)| self.removed.contains(host));
+        for (host, group) in hosts.iter().chain(readded.iter()) {
+            self.add_host(host, group);
+        }
+        self.readded.extend(readded);
+        if !hosts.is_empty() {
+            self.actions.push(Action::AddHosts(hosts));
+        }
+    }
+
+    /// Forgets the removal of the host once its tasks stopped, starting the new ones if it was
+    /// added back in the meantime.
+    pub fn finish_removal(&mut self, host: &str) {
+        self.removed.remove(host);
+        if let Some(group) = self.readded.remove(host) {
+            self.actions
+                .push(Action::AddHosts(vec![(host.to_string(), group)]));
+        }
+    }
+
+    /// Stops monitoring the hosts, forgetting everything about them but their log.
+    pub fn remove_hosts(&mut self, hosts: Vec<String>) {
+        let hosts: Vec<String> = hosts
+            .into_iter()
+            .filter(|host| self.hosts.contains_key(host))
+            .collect();
+        if hosts.is_empty() {
+            return;
+        }
+
+        self.history.push(Entry::new(
+            Level::Info,
+            None,
+            format!("Removing {} ({})", nodes::fold(&hosts), hosts.len()),
+        ));
+        for host in hosts.iter() {
+            self.hosts.remove(host);
+            self.marked.remove(host);
+            self.readded.remove(host);
+            self.removed.insert(host.clone());
+        }
+        if self.popup.as_ref().is_some_and(|host| hosts.contains(host)) {
+            self.popup = None;
+        }
+        let last = self.rows().len().checked_sub(1);
+        if let Some(selected) = self.table_state.selected() {
+            self.table_state.select(last.map(|last| selected.min(last)));
+        }
+        self.actions.push(Action::RemoveHosts(hosts));
+        // Resolve the alerts firing on the removed hosts right away
+        self.check_alerts();
+    }
+
+    /// Runs the drift checks on the marked hosts, or on every host if none is marked.
+    pub fn start_drift(&mut self) {
+        let hosts = self.target_hosts();
+        let id = self.drift.as_ref().map_or(0, |run| run.id + 1);
+        if self.drift.is_some() || self.command.take().is_some() {
+            self.actions.push(Action::CancelExec);
+        }
+        self.drift = Some(DriftRun::new(id, self.checks.clone(), &hosts));
+        self.actions.push(Action::Drift {
+            id,
+            commands: self
+                .checks
+                .iter()
+                .map(|check| check.command.clone())
+                .collect(),
+            hosts,
+        });
+    }
+
+    /// Closes the drift report, stopping the checks still running.
+    pub fn close_drift(&mut self) {
+        if self.drift.take().is_some() {
+            self.actions.push(Action::CancelExec);
+        }
+    }
+
+    pub fn set_drift_result(
+        &mut self,
+        host: &str,
+        id: u64,
+        check: usize,
+        result: AppResult<String>,
+    ) {
+        if let Some(run) = self.drift.as_mut().filter(|run| run.id == id) {
+            run.set_result(host, check, result);
+        }
+    }
+
+    /// Closes the command results, stopping the command if it is still running.
+    pub fn close_command(&mut self) {
+        if self.command.take().is_some() {
+            self.actions.push(Action::CancelExec);
+        }
+    }
+
+    pub fn push_command_output(&mut self, host: &str, id: u64, line: OutputLine) {
+        if let Some(result) = self
+            .command
+            .as_mut()
+            .filter(|run| run.id == id)
+            .and_then(|run| run.result_mut(host))
+        {
+            result.push(line);
+        }
+    }
+
+    pub fn set_command_status(&mut self, host: &str, id: u64, status: CommandStatus) {
+        if let Some(result) = self
+            .command
+            .as_mut()
+            .filter(|run| run.id == id)
+            .and_then(|run| run.result_mut(host))
+        {
+            result.status = status;
+        }
+    }
+
+    /// Registers a host as part of the given group.
+    pub fn add_host(&mut self, host: &str, group: &str) {
+        self.hosts.insert(host.to_string(), Host::new(group));
+    }
+
+    /// Host to update, registering it if unknown.
+    fn update_host(&mut self, host: &str) -> &mut Host {
         self.hosts
             .entry(host.to_string())
-            .and_modify(|v| *v = state)
-            .or_insert(HostState::Down(error.to_string()));
+            .or_insert_with(|| Host::new(""))
+    }
+
+    /// Changes the state of the host, logging the change if it is of a different kind or comes
+    /// with a different error.
+    fn set_host_state(&mut self, name: &str, state: HostState) {
+        let host = self.update_host(name);
+        let changed = std::mem::discriminant(&host.state) != std::mem::discriminant(&state)
+            || host.state.error() != state.error();
+        let (level, message) = state.describe();
+        // Only load samples refresh the data, so that a hung host still goes stale
+        if let HostState::Up(_) = state {
+            host.updated = Instant::now();
+        }
+        host.state = state;
+
+        if changed {
+            self.history.push(Entry::new(level, Some(name), message));
+        }
+    }
+
+    /// Whether the host is up but did not report any data for longer than `stale_after`.
+    pub fn is_stale(&self, host: &Host) -> bool {
+        matches!(host.state, HostState::Up(_)) && host.updated.elapsed() > self.stale_after
+    }
+
+    /// Name of the state of the host, hosts up being stale when their data is too old.
+    pub fn state_name(&self, host: &Host) -> &'static str {
+        match host.state {
+            HostState::Up(_) if self.is_stale(host) => "stale",
+            HostState::Queued => "queued",
+            HostState::Connecting => "connecting",
+            HostState::Authenticating => "authenticating",
+            HostState::Up(_) => "up",
+            HostState::Degraded(_) => "degraded",
+            HostState::Backoff(..) => "backoff",
+            HostState::Down(_) => "down",
+        }
+    }
+
+    pub fn set_host_queued(&mut self, host: &str) {
+        self.set_host_state(host, HostState::Queued);
+    }
+
+    pub fn set_host_connecting(&mut self, host: &str) {
+        self.set_host_state(host, HostState::Connecting);
+    }
+
+    pub fn set_host_authenticating(&mut self, host: &str) {
+        self.set_host_state(host, HostState::Authenticating);
+    }
+
+    pub fn set_host_connected(&mut self, host: &str, handshake: Duration) {
+        self.set_host_state(host, HostState::Up(String::new()));
+        self.update_host(host).handshake = Some(handshake);
+    }
+
+    pub fn push_host_latency(&mut self, host: &str, latency: Duration) {
+        let history = &mut self.update_host(host).latency;
+        if history.len() == LATENCY_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(latency);
+    }
+
+    pub fn set_host_degraded(&mut self, host: &str, error: Error) {
+        self.set_host_state(host, HostState::Degraded(error));
+    }
+
+    pub fn set_host_backoff(&mut self, host: &str, delay: Duration, error: Error) {
+        self.set_host_state(host, HostState::Backoff(delay, error));
+    }
+
+    pub fn set_host_status(&mut self, host: &str, load: &str) {
+        self.set_host_state(host, HostState::Up(load.to_string()));
+    }
+
+    pub fn set_host_inventory(&mut self, host: &str, inventory: Inventory) {
+        self.update_host(host).inventory = Some(inventory);
+    }
+
+    /// Updates the Lustre facts of the inventory, sampled more often than the rest.
+    pub fn set_host_lustre(&mut self, host: &str, mounts: Option<String>, health: Option<String>) {
+        let inventory = self
+            .update_host(host)
+            .inventory
+            .get_or_insert_with(Inventory::default);
+        inventory.lustre = mounts;
+        inventory.lustre_health = health;
+    }
+
+    pub fn log(&mut self, entry: Entry) {
+        self.history.push(entry);
+    }
+
+    pub fn set_host_memory(&mut self, host: &str, total: u64, available: u64) {
+        self.update_host(host).memory = Some((total, available));
+    }
+
+    pub fn set_host_error(&mut self, host: &str, error: Error) {
+        self.set_host_state(host, HostState::Down(error));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertConfig;
+    use crate::event::LoadEvent;
+    use crate::handler::handle_load_events;
+
+    fn host(name: &str) -> (String, String) {
+        (name.to_string(), name.to_string())
+    }
+
+    #[test]
+    fn readded_hosts_wait_for_their_removal() {
+        let mut app = App::new();
+        app.add_hosts(vec![host("n1")]);
+        app.remove_hosts(vec![String::from("n1")]);
+        app.take_actions();
+
+        app.add_hosts(vec![host("n1")]);
+        assert!(app.hosts.contains_key("n1"));
+        assert!(app.take_actions().is_empty());
+
+        // Events of the removed tasks still queued
+        handle_load_events(
+            "n1",
+            LoadEvent::Load("1.00 1.00 1.00 1/1 1".into()),
+            &mut app,
+        )
+        .unwrap();
+        assert!(matches!(app.hosts["n1"].state, HostState::Connecting));
+
+        app.finish_removal("n1");
+        assert!(matches!(
+            &app.take_actions()[..],
+            [Action::AddHosts(hosts)] if hosts == &[host("n1")]
+        ));
+        handle_load_events(
+            "n1",
+            LoadEvent::Load("1.00 1.00 1.00 1/1 1".into()),
+            &mut app,
+        )
+        .unwrap();
+        assert!(matches!(app.hosts["n1"].state, HostState::Up(_)));
+    }
+
+    #[test]
+    fn removing_hosts_resolves_their_alerts() {
+        let mut app = App::new();
+        app.alerts = Alerts::from_config(&[AlertConfig {
+            name: Some(String::from("busy")),
+            rule: String::from("load1 > 1"),
+            hook: Some(String::from("true")),
+            clear: None,
+        }])
+        .unwrap();
+        app.add_hosts(vec![host("n1")]);
+        app.set_host_status("n1", "4.00 1.00 1.00 1/1 1");
+        app.check_alerts();
+        assert_eq!(app.alerts.firing_count("n1"), 1);
+        app.take_actions();
+
+        app.remove_hosts(vec![String::from("n1")]);
+        let statuses: Vec<String> = app
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Hook { env, .. } => env
+                    .into_iter()
+                    .find(|(key, _)| *key == "JBTOP_STATUS")
+                    .map(|(_, status)| status),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(statuses, ["resolved"]);
+        assert_eq!(app.alerts.firing_count("n1"), 0);
+    }
+
+    #[test]
+    fn only_load_samples_keep_hosts_fresh() {
+        let mut app = App::new();
+        app.stale_after = Duration::ZERO;
+        app.add_hosts(vec![host("n1")]);
+        app.set_host_status("n1", "1.00 1.00 1.00 1/1 1");
+        std::thread::sleep(Duration::from_millis(5));
+        app.set_host_lustre("n1", Some(String::from("/scratch")), None);
+        app.set_host_memory("n1", 1024, 512);
+        app.push_host_latency("n1", Duration::from_millis(1));
+        assert!(app.is_stale(&app.hosts["n1"]));
     }
 }
@@ -0,0 +1,67 @@
+use clap::{Parser, ValueEnum};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// How the hosts are sampled.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SamplerMode {
+    /// Run a new command on the host for every sample
+    Exec,
+    /// Keep a single command running on the host, printing samples as it goes
+    Stream,
+}
+
+/// Command line arguments.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Nodeset of the hosts to monitor, e.g. `node[001-128],login1`.
+    ///
+    /// Each comma-separated element forms a group of hosts.
+    #[arg(required_unless_present = "replay")]
+    pub nodeset: Option<String>,
+
+    /// Maximum number of connections established at the same time
+    #[arg(short, long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fanout: u32,
+
+    /// Time between two samples of a host, in milliseconds
+    #[arg(short, long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+
+    /// Time after which a sampling command is abandoned, in milliseconds
+    #[arg(short, long, default_value_t = 5000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+
+    /// Read the configuration from FILE instead of `~/.config/jbtop/config.yaml`
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Colour theme: dark, light, high-contrast, colour-blind or one defined in the configuration
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+
+    /// Number of intervals without data after which a host is shown as stale
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub stale_after: u32,
+
+    /// How the hosts are sampled
+    #[arg(long, value_enum, default_value_t = SamplerMode::Exec)]
+    pub sampler: SamplerMode,
+
+    /// Record every monitoring event to FILE
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay a session recorded with `--record` instead of connecting to hosts
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Run headless and expose the host metrics on `http://ADDR/metrics` for Prometheus
+    #[arg(long, value_name = "ADDR", conflicts_with = "replay")]
+    pub serve: Option<SocketAddr>,
+
+    /// Initial replay speed multiplier
+    #[arg(long, default_value_t = 1.0, requires = "replay")]
+    pub speed: f64,
+}
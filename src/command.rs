@@ -0,0 +1,340 @@
+use crate::error::Error;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Number of lines of output kept per host, older lines are dropped.
+const MAX_LINES: usize = 1000;
+
+/// A line of output of a command.
+#[derive(Clone, Debug)]
+pub enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+impl OutputLine {
+    pub fn text(&self) -> &str {
+        match self {
+            OutputLine::Stdout(line) | OutputLine::Stderr(line) => line,
+        }
+    }
+}
+
+/// State of an ad-hoc command on a single host.
+#[derive(Clone, Debug)]
+pub enum CommandStatus {
+    Running,
+    Exited(u32),
+    Failed(Error),
+}
+
+/// Output of an ad-hoc command on a single host, gathered as it is streamed.
+#[derive(Clone, Debug)]
+pub struct CommandResult {
+    pub status: CommandStatus,
+    pub lines: VecDeque<OutputLine>,
+}
+
+impl CommandResult {
+    pub fn new() -> Self {
+        Self {
+            status: CommandStatus::Running,
+            lines: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, line: OutputLine) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Text shown for the host: stdout followed by stderr, or the reason the command could not
+    /// run. `None` while the command is running and has not output anything.
+    pub fn output(&self) -> Option<String> {
+        if let CommandStatus::Failed(error) = &self.status {
+            return Some(error.to_string());
+        }
+        if matches!(self.status, CommandStatus::Running) && self.lines.is_empty() {
+            return None;
+        }
+
+        let stdout = self
+            .lines
+            .iter()
+            .filter(|line| matches!(line, OutputLine::Stdout(_)));
+        let stderr = self
+            .lines
+            .iter()
+            .filter(|line| matches!(line, OutputLine::Stderr(_)));
+
+        Some(
+            stdout
+                .chain(stderr)
+                .map(OutputLine::text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+}
+
+impl Default for CommandResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An ad-hoc command run across hosts, with the results gathered so far.
+#[derive(Debug)]
+pub struct CommandRun {
+    /// Identifies the run, results from previous runs are discarded.
+    pub id: u64,
+    pub command: String,
+    pub results: BTreeMap<String, CommandResult>,
+    /// Show the differences with the most common output instead of the outputs themselves.
+    pub show_diff: bool,
+    /// Group hosts with identical output, instead of showing the latest lines of every host.
+    pub grouped: bool,
+    pub scroll: u16,
+    /// Output groups computed since the results last changed.
+    groups: Option<Vec<OutputGroup>>,
+}
+
+/// Hosts sharing the same output.
+#[derive(Clone, Debug)]
+pub struct OutputGroup {
+    pub hosts: Vec<String>,
+    pub output: String,
+    /// Differences with the output of the largest group, once computed.
+    pub diff: Option<Vec<DiffLine>>,
+}
+
+impl CommandRun {
+    pub fn new(id: u64, command: &str, hosts: &[String]) -> Self {
+        Self {
+            id,
+            command: command.to_string(),
+            results: hosts
+                .iter()
+                .map(|host| (host.clone(), CommandResult::new()))
+                .collect(),
+            show_diff: false,
+            grouped: true,
+            scroll: 0,
+            groups: None,
+        }
+    }
+
+    /// Number of hosts the command is still running on.
+    pub fn running(&self) -> usize {
+        self.results
+            .values()
+            .filter(|result| matches!(result.status, CommandStatus::Running))
+            .count()
+    }
+
+    /// Result of the command on the host, if it runs there.
+    pub fn result_mut(&mut self, host: &str) -> Option<&mut CommandResult> {
+        // The result is about to change
+        self.groups = None;
+        self.results.get_mut(host)
+    }
+
+    /// Hosts grouped by identical output, largest group first. In diff mode, the groups come
+    /// with their differences with the largest one.
+    ///
+    /// Groups and differences are only computed again once the results change.
+    pub fn groups(&mut self) -> &[OutputGroup] {
+        let groups = self.groups.get_or_insert_with(|| {
+            let mut groups = BTreeMap::<String, Vec<String>>::new();
+            for (host, result) in self.results.iter() {
+                if let Some(output) = result.output() {
+                    groups.entry(output).or_default().push(host.clone());
+                }
+            }
+
+            let mut groups: Vec<OutputGroup> = groups
+                .into_iter()
+                .map(|(output, hosts)| OutputGroup {
+                    hosts,
+                    output,
+                    diff: None,
+                })
+                .collect();
+            groups.sort_by_key(|group| std::cmp::Reverse(group.hosts.len()));
+            groups
+        });
+
+        if let (true, [reference, others @ ..]) = (self.show_diff, groups.as_mut_slice()) {
+            for group in others.iter_mut().filter(|group| group.diff.is_none()) {
+                group.diff = Some(diff(&reference.output, &group.output));
+            }
+        }
+
+        groups
+    }
+
+    /// Hosts grouped by exit code. Hosts on which the command could not run are not listed.
+    pub fn exit_codes(&self) -> BTreeMap<u32, Vec<&str>> {
+        let mut codes = BTreeMap::<u32, Vec<&str>>::new();
+        for (host, result) in self.results.iter() {
+            if let CommandStatus::Exited(code) = result.status {
+                codes.entry(code).or_default().push(host);
+            }
+        }
+        codes
+    }
+}
+
+/// A line of a diff between two outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Computes a line diff turning `old` into `new`, using their longest common subsequence.
+pub fn diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines.extend(
+        old[i..]
+            .iter()
+            .map(|line| DiffLine::Removed(line.to_string())),
+    );
+    lines.extend(
+        new[j..]
+            .iter()
+            .map(|line| DiffLine::Added(line.to_string())),
+    );
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffLine::{Added, Removed, Same};
+
+    fn lines(lines: &[&str]) -> String {
+        lines.join("\n")
+    }
+
+    #[test]
+    fn diffs_identical_outputs() {
+        assert_eq!(diff("a\nb", "a\nb"), [Same("a".into()), Same("b".into())]);
+        assert!(diff("", "").is_empty());
+    }
+
+    #[test]
+    fn diffs_changed_lines() {
+        assert_eq!(
+            diff(
+                &lines(&["a", "b", "c", "d"]),
+                &lines(&["a", "c", "e", "d", "f"])
+            ),
+            [
+                Same("a".into()),
+                Removed("b".into()),
+                Same("c".into()),
+                Added("e".into()),
+                Same("d".into()),
+                Added("f".into()),
+            ]
+        );
+        assert_eq!(diff("a\nb", ""), [Removed("a".into()), Removed("b".into())]);
+        assert_eq!(diff("", "a"), [Added("a".into())]);
+    }
+
+    #[test]
+    fn diffs_keep_every_line_of_both_outputs() {
+        let (old, new) = ("x\ny\nz\nx\ny", "y\nx\nz\ny\ny\nx");
+        let diff = diff(old, new);
+        let side = |keep: fn(&DiffLine) -> Option<&String>| {
+            diff.iter()
+                .filter_map(keep)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(
+            side(|line| match line {
+                Same(text) | Removed(text) => Some(text),
+                Added(_) => None,
+            }),
+            old
+        );
+        assert_eq!(
+            side(|line| match line {
+                Same(text) | Added(text) => Some(text),
+                Removed(_) => None,
+            }),
+            new
+        );
+        // The longest common subsequence has 3 lines
+        assert_eq!(
+            diff.iter().filter(|line| matches!(line, Same(_))).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn groups_hosts_by_output() {
+        let hosts: Vec<String> = ["n1", "n2", "n3", "n4"].map(String::from).to_vec();
+        let mut run = CommandRun::new(0, "uname -r", &hosts);
+        for (host, output) in [("n1", "5.14"), ("n2", "6.1"), ("n3", "5.14")] {
+            let result = run.result_mut(host).unwrap();
+            result.push(OutputLine::Stdout(output.into()));
+            result.status = CommandStatus::Exited(0);
+        }
+
+        let groups = run.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].hosts, ["n1", "n3"]);
+        assert_eq!(groups[0].output, "5.14");
+        assert_eq!(groups[1].hosts, ["n2"]);
+        assert_eq!(groups[1].output, "6.1");
+        assert!(groups[1].diff.is_none());
+
+        run.show_diff = true;
+        assert_eq!(
+            run.groups()[1].diff.as_deref(),
+            Some(&[Removed("5.14".into()), Added("6.1".into())][..])
+        );
+
+        // Groups are computed again once a result changes
+        run.result_mut("n4")
+            .unwrap()
+            .push(OutputLine::Stdout("5.14".into()));
+        assert_eq!(run.groups()[0].hosts, ["n1", "n3", "n4"]);
+    }
+}
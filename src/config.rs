@@ -0,0 +1,105 @@
+use crate::app::AppResult;
+use crate::error::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub alerts: Vec<AlertConfig>,
+    /// Checks compared across hosts to detect configuration drift.
+    pub drift: Vec<CheckConfig>,
+    pub keymap: KeymapConfig,
+    /// Name of the theme, built-in or defined in `themes`.
+    pub theme: Option<String>,
+    /// User-defined themes, by name.
+    pub themes: BTreeMap<String, ThemeConfig>,
+}
+
+/// An alert, as written in the configuration file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    /// Name shown in the interface, defaults to the rule itself.
+    pub name: Option<String>,
+    /// Condition raising the alert, e.g. `load1 > 8 for 60s` or `state == down`.
+    pub rule: String,
+    /// Shell command run when the alert fires or clears on a host.
+    pub hook: Option<String>,
+    /// Time the condition must stay false before the alert clears, e.g. `2m`.
+    pub clear: Option<String>,
+}
+
+/// A drift check, as written in the configuration file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckConfig {
+    /// Name shown in the report, defaults to the command itself.
+    pub name: Option<String>,
+    /// Shell command whose output should be the same on every host, e.g. `uname -r`.
+    pub command: String,
+}
+
+/// Key bindings, as written in the configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    /// Bindings to start from: `default`, `vim` or `htop`.
+    pub preset: Option<String>,
+    /// Keys of the actions to remap, e.g. `quit: [q, ctrl-c]`. They replace the keys of the preset.
+    pub bindings: BTreeMap<String, Keys>,
+}
+
+/// One or several keys, e.g. `ctrl-d` or `[j, down]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keys {
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        match self {
+            Keys::One(key) => std::slice::from_ref(key).iter(),
+            Keys::Many(keys) => keys.iter(),
+        }
+    }
+}
+
+/// A user-defined theme, as written in the configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Built-in theme providing the colours that are not given, `dark` by default.
+    pub base: Option<String>,
+    /// Colours by name, e.g. `critical: "#ff5f5f"` or `accent: lightblue`.
+    pub colors: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Location of the configuration file when none is given: `jbtop/config.yaml` in the
+    /// configuration directory of the user.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("jbtop").join("config.yaml"))
+    }
+
+    /// Reads the configuration from `path`, or from the default location if there is a file
+    /// there. The default configuration is used otherwise.
+    pub fn load(path: Option<&Path>) -> AppResult<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|e| Error::Parse(format!("{}: {}", path.display(), e)))
+    }
+}
@@ -0,0 +1,181 @@
+use crate::app::AppResult;
+use crate::command::{self, DiffLine};
+use crate::config::CheckConfig;
+use crate::error::Error;
+use std::collections::{BTreeMap, HashMap};
+
+/// Checks run when none are configured.
+pub static DEFAULT_CHECKS: &[(&str, &str)] = &[
+    ("kernel", "uname -r"),
+    (
+        "packages",
+        "if command -v rpm >/dev/null; then rpm -qa; else dpkg-query -W; fi | sort | md5sum | cut -d' ' -f1",
+    ),
+    (
+        "sysctl",
+        "for key in vm.swappiness vm.overcommit_memory vm.zone_reclaim_mode \
+         kernel.numa_balancing kernel.pid_max net.core.rmem_max net.core.wmem_max \
+         net.ipv4.tcp_congestion_control; do sysctl $key 2>/dev/null || echo \"$key unset\"; done",
+    ),
+    (
+        "mounts",
+        "awk '$2 !~ \"^/(proc|sys|run|dev)(/|$)\" {print $2, $3}' /proc/mounts | sort -u",
+    ),
+];
+
+/// A command whose output is expected to be the same on every host.
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: String,
+    pub command: String,
+}
+
+impl Check {
+    /// Checks from the configuration file, or [`DEFAULT_CHECKS`] if none are configured.
+    pub fn from_config(config: &[CheckConfig]) -> Vec<Self> {
+        if config.is_empty() {
+            return DEFAULT_CHECKS
+                .iter()
+                .map(|(name, command)| Self {
+                    name: name.to_string(),
+                    command: command.to_string(),
+                })
+                .collect();
+        }
+
+        config
+            .iter()
+            .map(|check| Self {
+                name: check.name.clone().unwrap_or_else(|| check.command.clone()),
+                command: check.command.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Hosts sharing the same result of a check.
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub hosts: Vec<String>,
+    pub result: AppResult<String>,
+    /// Lines differing from the output of the majority, when either output spans several lines.
+    pub diff: Option<Vec<DiffLine>>,
+}
+
+/// Results of a check: the most common one, and the ones deviating from it, largest first.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub majority: Option<Group>,
+    pub outliers: Vec<Group>,
+    /// Number of hosts the check has not completed on yet.
+    pub pending: usize,
+}
+
+/// Checks run across hosts to find the hosts whose configuration drifted from the others.
+#[derive(Debug)]
+pub struct DriftRun {
+    /// Identifies the run, results from previous runs are discarded.
+    pub id: u64,
+    pub checks: Vec<Check>,
+    /// Result of every check, in order, on every host.
+    results: BTreeMap<String, Vec<Option<AppResult<String>>>>,
+    /// Report of every check, computed since its results last changed.
+    reports: Vec<Option<Report>>,
+    pub scroll: u16,
+}
+
+impl DriftRun {
+    pub fn new(id: u64, checks: Vec<Check>, hosts: &[String]) -> Self {
+        let results = hosts
+            .iter()
+            .map(|host| (host.clone(), vec![None; checks.len()]))
+            .collect();
+        Self {
+            id,
+            reports: vec![None; checks.len()],
+            checks,
+            results,
+            scroll: 0,
+        }
+    }
+
+    /// Records the result of a check on a host. Trailing whitespace of outputs is ignored.
+    pub fn set_result(&mut self, host: &str, check: usize, result: AppResult<String>) {
+        if let Some(slot) = self
+            .results
+            .get_mut(host)
+            .and_then(|results| results.get_mut(check))
+        {
+            *slot = Some(result.map(|output| output.trim_end().to_string()));
+            self.reports[check] = None;
+        }
+    }
+
+    pub fn hosts(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Number of hosts every check completed on.
+    pub fn done(&self) -> usize {
+        self.results
+            .values()
+            .filter(|results| results.iter().all(Option::is_some))
+            .count()
+    }
+
+    /// Hosts grouped by result of the given check. The majority is the largest group, ties going
+    /// to the group of the first host in name order.
+    ///
+    /// Reports are only computed again once a result of the check changes.
+    pub fn report(&mut self, check: usize) -> &Report {
+        let results = &self.results;
+        self.reports[check].get_or_insert_with(|| Self::compute_report(results, check))
+    }
+
+    fn compute_report(
+        results: &BTreeMap<String, Vec<Option<AppResult<String>>>>,
+        check: usize,
+    ) -> Report {
+        let mut groups = HashMap::<Result<&str, &Error>, Vec<String>>::new();
+        let mut pending = 0;
+        for (host, results) in results.iter() {
+            match results.get(check) {
+                Some(Some(result)) => groups
+                    .entry(result.as_deref())
+                    .or_default()
+                    .push(host.clone()),
+                _ => pending += 1,
+            }
+        }
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        // Hosts are iterated in name order, so the first host identifies the group
+        groups.sort_by(|(_, a), (_, b)| b.len().cmp(&a.len()).then_with(|| a[0].cmp(&b[0])));
+
+        let reference = groups.first().and_then(|(result, _)| result.ok());
+        let mut groups = groups
+            .into_iter()
+            .enumerate()
+            .map(|(index, (result, hosts))| {
+                let diff = match (index, reference, result) {
+                    (0, _, _) => None,
+                    (_, Some(reference), Ok(output))
+                        if reference.contains('\n') || output.contains('\n') =>
+                    {
+                        Some(command::diff(reference, output))
+                    }
+                    _ => None,
+                };
+                Group {
+                    hosts,
+                    result: result.map(str::to_string).map_err(Error::clone),
+                    diff,
+                }
+            });
+        Report {
+            majority: groups.next(),
+            outliers: groups.collect(),
+            pending,
+        }
+    }
+}
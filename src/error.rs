@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Errors, classified by what failed so that they can be told apart in the interface.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// The hostname could not be resolved.
+    Dns(String),
+    /// The TCP connection could not be established, or was lost.
+    Tcp(String),
+    /// The key could not be loaded, or was rejected by the host.
+    Auth(String),
+    /// The key of the host was refused, or no key exchange could be agreed on.
+    HostKey(String),
+    /// A channel could not be opened on the session.
+    Channel(String),
+    /// A command could not be started, or did not exit cleanly.
+    Exec(String),
+    /// An operation did not complete within the given time.
+    Timeout(Duration),
+    /// Invalid input, be it from the command line, a file or a host.
+    Parse(String),
+    /// A command exited with the given non-zero code, along with its stderr.
+    Exit(u32, String),
+    /// Local input/output error, on the terminal or files.
+    Io(String),
+}
+
+/// Kinds of [`Error`], without their details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorClass {
+    Dns,
+    Tcp,
+    Auth,
+    HostKey,
+    Channel,
+    Exec,
+    Timeout,
+    Parse,
+    Exit,
+    Io,
+}
+
+impl ErrorClass {
+    pub const ALL: [ErrorClass; 10] = [
+        ErrorClass::Dns,
+        ErrorClass::Tcp,
+        ErrorClass::Auth,
+        ErrorClass::HostKey,
+        ErrorClass::Channel,
+        ErrorClass::Exec,
+        ErrorClass::Timeout,
+        ErrorClass::Parse,
+        ErrorClass::Exit,
+        ErrorClass::Io,
+    ];
+
+    /// Short name of the class, as shown in the interface and stored in recordings.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorClass::Dns => "dns",
+            ErrorClass::Tcp => "tcp",
+            ErrorClass::Auth => "auth",
+            ErrorClass::HostKey => "host-key",
+            ErrorClass::Channel => "channel",
+            ErrorClass::Exec => "exec",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Parse => "parse",
+            ErrorClass::Exit => "exit",
+            ErrorClass::Io => "io",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|class| class.name() == name)
+    }
+}
+
+impl Error {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Error::Dns(_) => ErrorClass::Dns,
+            Error::Tcp(_) => ErrorClass::Tcp,
+            Error::Auth(_) => ErrorClass::Auth,
+            Error::HostKey(_) => ErrorClass::HostKey,
+            Error::Channel(_) => ErrorClass::Channel,
+            Error::Exec(_) => ErrorClass::Exec,
+            Error::Timeout(_) => ErrorClass::Timeout,
+            Error::Parse(_) => ErrorClass::Parse,
+            Error::Exit(..) => ErrorClass::Exit,
+            Error::Io(_) => ErrorClass::Io,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Dns(e) => write!(f, "Could not resolve host: {}", e),
+            Error::Tcp(e) => write!(f, "Connection failed: {}", e),
+            Error::Auth(e) => write!(f, "Authentication failed: {}", e),
+            Error::HostKey(e) => write!(f, "Key exchange failed: {}", e),
+            Error::Channel(e) => write!(f, "Could not open channel: {}", e),
+            Error::Exec(e) | Error::Parse(e) | Error::Io(e) => write!(f, "{}", e),
+            Error::Timeout(timeout) => write!(f, "Timed out after {:.1}s", timeout.as_secs_f64()),
+            Error::Exit(code, stderr) if stderr.is_empty() => {
+                write!(f, "Exited with code {}", code)
+            }
+            Error::Exit(code, stderr) => write!(f, "Exited with code {}: {}", code, stderr),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+/// Classifies errors of the SSH library by the step of the connection they come from.
+///
+/// Callers knowing better, e.g. when opening a channel, should classify the error themselves.
+impl From<russh::Error> for Error {
+    fn from(error: russh::Error) -> Self {
+        use russh::Error as E;
+
+        let message = error.to_string();
+        match error {
+            E::UnknownKey
+            | E::WrongServerSig
+            | E::KeyChanged { .. }
+            | E::KexInit
+            | E::Kex
+            | E::UnknownAlgo
+            | E::NoCommonKexAlgo
+            | E::NoCommonKeyAlgo
+            | E::NoCommonCipher
+            | E::NoCommonCompression
+            | E::NoCommonMac
+            | E::StrictKeyExchangeViolation { .. } => Error::HostKey(message),
+            E::CouldNotReadKey | E::NotAuthenticated | E::NoAuthMethod | E::Keys(_) => {
+                Error::Auth(message)
+            }
+            E::WrongChannel | E::ChannelOpenFailure(_) => Error::Channel(message),
+            _ => Error::Tcp(message),
+        }
+    }
+}
+
+impl From<russh_keys::Error> for Error {
+    fn from(error: russh_keys::Error) -> Self {
+        Error::Auth(error.to_string())
+    }
+}
@@ -1,22 +1,82 @@
+use crate::app::{AppResult, LoadAverage};
+use crate::error::Error;
+use crate::history::Entry;
+use crate::inventory::{self, Inventory};
+use crate::record::ReplayCommand;
+use crate::sampler::{self, SampleParser};
 use crate::ssh;
 use crossterm::event::{Event as CrosstermEvent, KeyEvent, MouseEvent};
 use futures::{FutureExt, StreamExt};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, MutexGuard, PoisonError};
 use tokio::{
-    sync::{mpsc, Mutex},
-    time::{interval, Duration},
+    sync::{
+        mpsc::{
+            self,
+            error::{SendError, TrySendError},
+        },
+        Notify, Semaphore,
+    },
+    time::{interval, interval_at, Duration, Instant, MissedTickBehavior},
 };
 
 #[derive(Clone, Debug)]
 pub enum LoadEvent {
     Load(String),
-    LoadError(String),
+    /// Time taken by the last sample, from opening the channel to the exit of the command.
+    ///
+    /// With a long-lived sampler, this is the time taken to start it and get its first sample,
+    /// then how late every following sample arrives compared to the sampling period.
+    Latency(Duration),
+    /// Total and available memory, in kB.
+    Memory(u64, u64),
+    /// Facts about the host, collected once connected.
+    Inventory(Box<Inventory>),
+    /// Lustre filesystems mounted and health of the Lustre modules, as in [`Inventory`].
+    Lustre(Option<String>, Option<String>),
+    LoadError(Error),
+}
+
+#[derive(Clone, Debug)]
+pub enum ReplayEvent {
+    /// The replay jumped backwards, all host state must be discarded.
+    Reset,
+    /// Current position in the recording.
+    Position {
+        position: Duration,
+        duration: Duration,
+        speed: f64,
+        paused: bool,
+    },
 }
 
 #[derive(Clone, Debug)]
 pub enum ConnectionEvent {
+    /// Waiting for other connections to complete before connecting.
+    Queued,
     Connecting,
-    Connected,
-    ConnectionError(String),
+    Authenticating,
+    /// Connected, after a handshake and authentication that took the given time.
+    Connected(Duration),
+    /// The connection failed, and will be retried after the given delay.
+    Backoff(Duration, Error),
+    /// The connection failed and will not be retried.
+    Failed(Error),
+}
+
+#[derive(Clone, Debug)]
+pub enum CommandEvent {
+    Stdout(String),
+    Stderr(String),
+    Exited(u32),
+    Failed(Error),
+}
+
+#[derive(Clone, Debug)]
+pub enum DriftEvent {
+    /// Output of the drift check with the given index.
+    Output(usize, String),
+    Failed(usize, Error),
 }
 
 /// Terminal events.
@@ -33,6 +93,285 @@ pub enum Event {
 
     HostStatus(String, ConnectionEvent),
     LoadStatus(String, LoadEvent),
+    /// Result of the ad-hoc command with the given id on a host.
+    CommandStatus(String, u64, CommandEvent),
+    /// Result of a check of the drift detection run with the given id on a host.
+    DriftStatus(String, u64, DriftEvent),
+    Replay(ReplayEvent),
+    /// The tasks of a host removed at runtime stopped, none of its events follow.
+    Removed(String),
+    /// Record of the `log` crate, captured to be shown in the interface.
+    Log(Entry),
+}
+
+/// Number of events queued before the tasks sending them wait for the interface to catch up.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Samples of a host of which only the latest matters.
+///
+/// Latencies are not coalesced, since every one of them makes it to the latency history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Metric {
+    /// The load, or the error preventing to sample it.
+    Load,
+    Memory,
+}
+
+impl Metric {
+    const ALL: [Metric; 2] = [Metric::Load, Metric::Memory];
+
+    fn of(event: &LoadEvent) -> Option<Self> {
+        match event {
+            LoadEvent::Load(_) | LoadEvent::LoadError(_) => Some(Metric::Load),
+            LoadEvent::Memory(..) => Some(Metric::Memory),
+            LoadEvent::Latency(_) | LoadEvent::Inventory(_) | LoadEvent::Lustre(..) => None,
+        }
+    }
+}
+
+/// Latest samples of the hosts, waiting to be received.
+#[derive(Debug, Default)]
+struct Samples {
+    latest: std::sync::Mutex<BTreeMap<(String, Metric), LoadEvent>>,
+    /// Wakes the receiver up when samples are added.
+    added: Notify,
+}
+
+impl Samples {
+    fn latest(&self) -> MutexGuard<'_, BTreeMap<(String, Metric), LoadEvent>> {
+        // The map is left consistent by every operation, even interrupted by a panic
+        self.latest.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Removes the samples of the host not received yet.
+    fn take_host(&self, host: &str) -> Vec<Event> {
+        let mut latest = self.latest();
+        Metric::ALL
+            .into_iter()
+            .filter_map(|metric| latest.remove_entry(&(host.to_string(), metric)))
+            .map(|((host, _), event)| Event::LoadStatus(host, event))
+            .collect()
+    }
+}
+
+/// Creates the channel of the events, holding at most `capacity` events.
+pub fn channel(capacity: usize) -> (EventSender, EventReceiver) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let samples = Arc::new(Samples::default());
+    (
+        EventSender {
+            sender,
+            samples: Arc::clone(&samples),
+        },
+        EventReceiver {
+            receiver,
+            samples,
+            pending: VecDeque::new(),
+        },
+    )
+}
+
+/// Sending end of the event channel.
+///
+/// Samples of the hosts are coalesced instead of queued: a sample replaces the previous one of the
+/// same host and metric if it was not received yet. Other events wait for room in the channel, so
+/// that the tasks sending them slow down to the pace of the interface.
+///
+/// Events of a host are received in the order they are sent: the samples of the host not
+/// received yet are queued before any other event of the host.
+#[derive(Clone, Debug)]
+pub struct EventSender {
+    sender: mpsc::Sender<Event>,
+    samples: Arc<Samples>,
+}
+
+impl EventSender {
+    /// Sends the event, waiting for room in the channel. Fails once the receiver is dropped.
+    pub async fn send(&self, event: Event) -> Result<(), SendError<Event>> {
+        for event in self.coalesce(event)? {
+            self.sender.send(event).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the event if there is room in the channel, dropping it otherwise.
+    pub fn try_send(&self, event: Event) -> Result<(), TrySendError<Event>> {
+        let events = self
+            .coalesce(event)
+            .map_err(|SendError(event)| TrySendError::Closed(event))?;
+        for event in events {
+            self.sender.try_send(event)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the samples with the latest ones, returning the events to queue in order.
+    fn coalesce(&self, event: Event) -> Result<Vec<Event>, SendError<Event>> {
+        if self.sender.is_closed() {
+            return Err(SendError(event));
+        }
+
+        match event {
+            Event::LoadStatus(host, load) => match Metric::of(&load) {
+                Some(metric) => {
+                    self.samples.latest().insert((host, metric), load);
+                    self.samples.added.notify_one();
+                    Ok(vec![])
+                }
+                None => {
+                    let mut events = self.samples.take_host(&host);
+                    events.push(Event::LoadStatus(host, load));
+                    Ok(events)
+                }
+            },
+            Event::HostStatus(ref host, _) | Event::Removed(ref host) => {
+                let mut events = self.samples.take_host(host);
+                events.push(event);
+                Ok(events)
+            }
+            // Samples recorded after the new position must not outlive the reset
+            Event::Replay(ReplayEvent::Reset) => {
+                self.samples.latest().clear();
+                Ok(vec![event])
+            }
+            event => Ok(vec![event]),
+        }
+    }
+
+    /// Waits for the receiver to be dropped.
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+}
+
+/// Receiving end of the event channel.
+#[derive(Debug)]
+pub struct EventReceiver {
+    receiver: mpsc::Receiver<Event>,
+    samples: Arc<Samples>,
+    /// Events taken from the channel and the latest samples but not returned yet.
+    pending: VecDeque<Event>,
+}
+
+impl EventReceiver {
+    /// Receives the next event, or `None` once every sender is dropped.
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+
+            tokio::select! {
+                biased;
+                event = self.receiver.recv() => return event,
+                _ = self.samples.added.notified() => {
+                    // Events queued before the samples are taken were sent before them, a reset
+                    // in particular must not wipe the samples that follow it
+                    while let Ok(event) = self.receiver.try_recv() {
+                        self.pending.push_back(event);
+                    }
+                    let samples = std::mem::take(&mut *self.samples.latest());
+                    self.pending.extend(
+                        samples
+                            .into_iter()
+                            .map(|((host, _), event)| Event::LoadStatus(host, event)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Delay before the first reconnection attempt.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// Longest delay between two reconnection attempts.
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// Number of channels failing to open in a row after which a session is considered dead.
+const MAX_CHANNEL_FAILURES: u32 = 3;
+
+/// Delay before the given reconnection attempt, doubling with each attempt.
+///
+/// Half of the delay is random, so that hosts lost at the same time do not reconnect in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(BACKOFF_MAX);
+    delay / 2 + (delay / 2).mul_f64(fastrand::f64())
+}
+
+/// Opens a channel on the session of the host, waiting for it to be connected.
+///
+/// Sessions failing to open [`MAX_CHANNEL_FAILURES`] channels in a row, or to open them within
+/// `timeout`, are discarded to have the connection handler reconnect.
+async fn open_channel(
+    connection: &ssh::Connection,
+    failures: &mut u32,
+    timeout: Duration,
+) -> AppResult<ssh::Channel> {
+    let session = connection.connected().await;
+    let channel = open_channel_within(&session, timeout).await;
+
+    match channel.is_ok() {
+        true => *failures = 0,
+        false => *failures += 1,
+    }
+    if *failures >= MAX_CHANNEL_FAILURES {
+        connection.discard(&session);
+        *failures = 0;
+    }
+
+    channel
+}
+
+/// Opens a channel on the session of the host, failing if it is not connected or if the channel
+/// is not opened within `timeout`.
+async fn try_open_channel(
+    connection: &ssh::Connection,
+    timeout: Duration,
+) -> AppResult<ssh::Channel> {
+    match connection.get() {
+        Some(session) => open_channel_within(&session, timeout).await,
+        None => Err(Error::Channel(String::from("Not connected"))),
+    }
+}
+
+/// Opens a channel on a session that may be unresponsive without noticing it was lost.
+async fn open_channel_within(session: &ssh::Session, timeout: Duration) -> AppResult<ssh::Channel> {
+    tokio::time::timeout(timeout, session.open_channel())
+        .await
+        .map_err(|_| Error::Timeout(timeout))?
+}
+
+/// Gathers the static facts about the host.
+async fn collect_inventory(
+    connection: &ssh::Connection,
+    timeout: Duration,
+) -> AppResult<Inventory> {
+    let mut channel = try_open_channel(connection, timeout).await?;
+    channel.set_timeout(Some(timeout));
+
+    // Facts that cannot be gathered are left out, the script only fails if the shell does
+    let (code, stdout, stderr) = channel.block_exec(inventory::SCRIPT).await?;
+    match code {
+        0 => Ok(Inventory::parse(&stdout)),
+        code => Err(Error::Exit(code, stderr)),
+    }
+}
+
+/// Gathers the Lustre facts about the host.
+async fn collect_lustre(
+    connection: &ssh::Connection,
+    failures: &mut u32,
+    timeout: Duration,
+) -> AppResult<Inventory> {
+    let mut channel = open_channel(connection, failures, timeout).await?;
+    channel.set_timeout(Some(timeout));
+
+    let (code, stdout, stderr) = channel.block_exec(inventory::LUSTRE_SCRIPT).await?;
+    match code {
+        0 => Ok(Inventory::parse(&stdout)),
+        code => Err(Error::Exit(code, stderr)),
+    }
 }
 
 /// Terminal event handler.
@@ -45,7 +384,7 @@ pub struct EventHandler {
 
 impl EventHandler {
     /// Constructs a new instance of [`EventHandler`] for the running terminal.
-    pub fn terminal(sender: mpsc::UnboundedSender<Event>, tick_rate: u64) -> Self {
+    pub fn terminal(sender: EventSender, tick_rate: u64) -> Self {
         let tick_rate = Duration::from_millis(tick_rate);
         let handler = tokio::spawn(async move {
             let mut reader = crossterm::event::EventStream::new();
@@ -58,27 +397,25 @@ impl EventHandler {
                     break;
                   }
                   _ = tick_delay => {
-                    sender.send(Event::Tick).unwrap();
+                    // Ticks are only worth sending when the interface keeps up
+                    let _ = sender.try_send(Event::Tick);
                   }
                   Some(Ok(evt)) = crossterm_event => {
-                    match evt {
+                    let event = match evt {
                       CrosstermEvent::Key(key) => {
-                        if key.kind == crossterm::event::KeyEventKind::Press {
-                          sender.send(Event::Key(key)).unwrap();
+                        if key.kind != crossterm::event::KeyEventKind::Press {
+                          continue;
                         }
+                        Event::Key(key)
                       },
-                      CrosstermEvent::Mouse(mouse) => {
-                        sender.send(Event::Mouse(mouse)).unwrap();
-                      },
-                      CrosstermEvent::Resize(x, y) => {
-                        sender.send(Event::Resize(x, y)).unwrap();
-                      },
-                      CrosstermEvent::FocusLost => {
-                      },
-                      CrosstermEvent::FocusGained => {
-                      },
-                      CrosstermEvent::Paste(_) => {
-                      },
+                      CrosstermEvent::Mouse(mouse) => Event::Mouse(mouse),
+                      CrosstermEvent::Resize(x, y) => Event::Resize(x, y),
+                      CrosstermEvent::FocusLost
+                      | CrosstermEvent::FocusGained
+                      | CrosstermEvent::Paste(_) => continue,
+                    };
+                    if sender.send(event).await.is_err() {
+                      break;
                     }
                   }
                 };
@@ -87,37 +424,94 @@ impl EventHandler {
         Self { handler }
     }
 
+    /// Keeps the session of the host up, reconnecting with an exponential backoff when it is
+    /// lost. Sessions are considered lost when the connection closes, or when the collectors
+    /// discard them after failing to open channels.
+    ///
+    /// Connections are only attempted with a permit from `fanout`, which bounds the number of
+    /// handshakes in progress at any time.
     pub fn connection(
-        sender: mpsc::UnboundedSender<Event>,
+        sender: EventSender,
         hostname: &str,
-        session: std::sync::Arc<Mutex<Option<ssh::Session>>>,
+        connection: ssh::Connection,
+        fanout: std::sync::Arc<Semaphore>,
+        timeout: Duration,
     ) -> Self {
         let _host = hostname.to_string();
         let handler = tokio::spawn(async move {
-            sender
-                .send(Event::HostStatus(
-                    _host.to_string(),
-                    ConnectionEvent::Connecting,
-                ))
-                .unwrap();
+            let send = |event| sender.send(Event::HostStatus(_host.to_string(), event));
+            let mut attempt = 0;
             let mut tick = interval(Duration::from_millis(1000));
             loop {
                 tick.tick().await;
-                let session_clone = std::sync::Arc::clone(&session);
-                let mut lock = session_clone.lock().await;
-                if lock.is_none() {
-                    match ssh::Session::new(&_host).await {
-                        Ok(ssh_handle) => {
-                            *lock = Some(ssh_handle);
+                match connection.get() {
+                    Some(session) if !session.is_closed() => continue,
+                    Some(session) => connection.discard(&session),
+                    None => (),
+                }
+
+                let _permit = match fanout.try_acquire() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        if send(ConnectionEvent::Queued).await.is_err() {
+                            return;
+                        }
+                        fanout.acquire().await.unwrap()
+                    }
+                };
+
+                if send(ConnectionEvent::Connecting).await.is_err() {
+                    return;
+                }
+                let start = Instant::now();
+                let authenticated = match ssh::Session::handshake(&_host).await {
+                    Ok(handshake) => {
+                        if send(ConnectionEvent::Authenticating).await.is_err() {
+                            return;
                         }
-                        Err(e) => {
-                            sender
-                                .send(Event::HostStatus(
-                                    _host.to_string(),
-                                    ConnectionEvent::ConnectionError(e.to_string()),
-                                ))
-                                .unwrap();
+                        ssh::Session::authenticate(handshake).await
+                    }
+                    Err(e) => Err(e),
+                };
+                drop(_permit);
+
+                match authenticated {
+                    Ok(Some(ssh_handle)) => {
+                        connection.set(ssh_handle);
+                        attempt = 0;
+                        if send(ConnectionEvent::Connected(start.elapsed()))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+
+                        // The inventory does not change while the host is up, it is only collected
+                        // on connection
+                        let event = match collect_inventory(&connection, timeout).await {
+                            Ok(inventory) => LoadEvent::Inventory(Box::new(inventory)),
+                            Err(e) => LoadEvent::LoadError(e),
+                        };
+                        let event = Event::LoadStatus(_host.to_string(), event);
+                        if sender.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => {
+                        let _ = send(ConnectionEvent::Failed(Error::Auth(String::from(
+                            "Key rejected",
+                        ))))
+                        .await;
+                        break;
+                    }
+                    Err(e) => {
+                        let delay = backoff(attempt);
+                        attempt += 1;
+                        if send(ConnectionEvent::Backoff(delay, e)).await.is_err() {
+                            return;
                         }
+                        tokio::time::sleep(delay).await;
+                        tick.reset_immediately();
                     }
                 }
             }
@@ -126,45 +520,384 @@ impl EventHandler {
         Self { handler }
     }
 
+    /// Samples the load of the host every `period`, running a new command every time.
     pub fn load(
-        sender: mpsc::UnboundedSender<Event>,
+        sender: EventSender,
         hostname: &str,
-        session: std::sync::Arc<Mutex<Option<ssh::Session>>>,
+        connection: ssh::Connection,
+        period: Duration,
+        timeout: Duration,
     ) -> Self {
         let _host = hostname.to_string();
         let handler = tokio::spawn(async move {
-            let mut tick = interval(Duration::from_millis(1000));
+            let send = |event| sender.send(Event::LoadStatus(_host.to_string(), event));
+            let mut failures = 0;
+            let mut tick = interval(period);
+            // Sampling resumes at the pace of the period once the host is back up
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+
+                let start = Instant::now();
+                let mut channel = match open_channel(&connection, &mut failures, timeout).await {
+                    Ok(channel) => channel,
+                    Err(e) => {
+                        if send(LoadEvent::LoadError(e)).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+                channel.set_timeout(Some(timeout));
+
+                let result = channel.block_exec("cat /proc/loadavg").await;
+                if result.is_ok() && send(LoadEvent::Latency(start.elapsed())).await.is_err() {
+                    return;
+                }
+
+                let event = match result {
+                    Ok((0, o, _)) => match o.parse::<LoadAverage>() {
+                        Ok(_) => LoadEvent::Load(o),
+                        Err(e) => LoadEvent::LoadError(e),
+                    },
+                    Ok((code, _, e)) => LoadEvent::LoadError(Error::Exit(code, e)),
+                    Err(e) => LoadEvent::LoadError(e),
+                };
+
+                if send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { handler }
+    }
+
+    /// Samples the host using a single long-lived command instead of one command per sample.
+    ///
+    /// The command is restarted whenever it dies or stops printing samples, as long as the
+    /// session is up.
+    pub fn sampler(
+        sender: EventSender,
+        hostname: &str,
+        connection: ssh::Connection,
+        period: Duration,
+        timeout: Duration,
+    ) -> Self {
+        let _host = hostname.to_string();
+        let handler = tokio::spawn(async move {
+            let send = |event| sender.send(Event::LoadStatus(_host.to_string(), event));
+            let mut failures = 0;
+            let mut tick = interval(period);
+            // Sampling resumes at the pace of the period once the host is back up
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
             loop {
                 tick.tick().await;
 
-                let session = session.lock().await;
-
-                if let Some(session) = session.as_ref() {
-                    let channel = session.open_channel().await;
-                    if let Err(e) = channel {
-                        sender
-                            .send(Event::LoadStatus(
-                                _host.to_string(),
-                                LoadEvent::LoadError(e.to_string()),
-                            ))
-                            .unwrap();
+                let mut last = Instant::now();
+                let mut channel = match open_channel(&connection, &mut failures, timeout).await {
+                    Ok(channel) => channel,
+                    Err(e) => {
+                        if send(LoadEvent::LoadError(e)).await.is_err() {
+                            return;
+                        }
                         continue;
                     }
+                };
+                channel.set_timeout(Some(period + timeout));
+
+                if let Err(e) = channel.stream(&sampler::script(period)).await {
+                    if send(LoadEvent::LoadError(e)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
 
-                    let event = match channel.unwrap().block_exec("cat /proc/loadavg").await {
-                        Ok((c, o, _)) if c == 0 => {
-                            Event::LoadStatus(_host.to_string(), LoadEvent::Load(o))
+                let mut parser = SampleParser::new();
+                let mut sampled = false;
+                let mut error = vec![];
+                let mut code = None;
+                let reason = loop {
+                    let line = match channel.next_output().await {
+                        Ok(Some(ssh::Output::Stdout(line))) => line,
+                        Ok(Some(ssh::Output::Stderr(line))) => {
+                            error.push(line);
+                            continue;
                         }
-                        Ok((_, _, e)) => {
-                            Event::LoadStatus(_host.to_string(), LoadEvent::LoadError(e))
+                        Ok(Some(ssh::Output::Exit(status))) => {
+                            code = Some(status);
+                            continue;
                         }
-                        Err(e) => Event::LoadStatus(
-                            _host.to_string(),
-                            LoadEvent::LoadError(e.to_string()),
-                        ),
+                        Ok(None) => match code {
+                            Some(code) => break Error::Exit(code, error.join("\n")),
+                            None => break Error::Exec(String::from("Sampler exited")),
+                        },
+                        Err(e) => break e,
                     };
 
-                    sender.send(event).unwrap();
+                    let sample = match parser.push(line) {
+                        Ok(Some(sample)) => sample,
+                        Ok(None) => continue,
+                        Err(e) => break e,
+                    };
+                    // The first sample is printed right away, the next ones after sleeping
+                    let latency = match sampled {
+                        true => last.elapsed().saturating_sub(period),
+                        false => last.elapsed(),
+                    };
+                    sampled = true;
+                    last = Instant::now();
+                    if send(LoadEvent::Latency(latency)).await.is_err() {
+                        return;
+                    }
+
+                    if let Some(load) = sample.load {
+                        let event = match load.parse::<LoadAverage>() {
+                            Ok(_) => LoadEvent::Load(load),
+                            Err(e) => LoadEvent::LoadError(e),
+                        };
+                        if send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                    if let Some((total, available)) = sample.memory {
+                        if send(LoadEvent::Memory(total, available)).await.is_err() {
+                            return;
+                        }
+                    }
+                };
+
+                if send(LoadEvent::LoadError(reason)).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { handler }
+    }
+
+    /// Samples the Lustre mounts and health of the host every `period`, starting one period after
+    /// the inventory is collected along with them.
+    pub fn lustre(
+        sender: EventSender,
+        hostname: &str,
+        connection: ssh::Connection,
+        period: Duration,
+        timeout: Duration,
+    ) -> Self {
+        let _host = hostname.to_string();
+        let handler = tokio::spawn(async move {
+            let send = |event| sender.send(Event::LoadStatus(_host.to_string(), event));
+            let mut failures = 0;
+            let mut tick = interval_at(Instant::now() + period, period);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+
+                // Failures are already reported by the load sampler
+                let inventory = match collect_lustre(&connection, &mut failures, timeout).await {
+                    Ok(inventory) => inventory,
+                    Err(e) => {
+                        log::debug!("Failed to sample the Lustre health of {}: {}", _host, e);
+                        continue;
+                    }
+                };
+
+                let event = LoadEvent::Lustre(inventory.lustre, inventory.lustre_health);
+                if send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { handler }
+    }
+
+    /// Runs an ad-hoc command on the host, streaming its output line by line.
+    ///
+    /// The command itself may run for as long as it needs, only opening the channel is bound by
+    /// `timeout`.
+    pub fn command(
+        sender: EventSender,
+        hostname: &str,
+        connection: ssh::Connection,
+        id: u64,
+        command: &str,
+        timeout: Duration,
+    ) -> Self {
+        let _host = hostname.to_string();
+        let command = command.to_string();
+        let handler = tokio::spawn(async move {
+            let send = |event| sender.send(Event::CommandStatus(_host.to_string(), id, event));
+
+            let mut channel = match try_open_channel(&connection, timeout).await {
+                Ok(channel) => channel,
+                Err(e) => {
+                    let _ = send(CommandEvent::Failed(e)).await;
+                    return;
+                }
+            };
+
+            if let Err(e) = channel.stream(&command).await {
+                let _ = send(CommandEvent::Failed(e)).await;
+                return;
+            }
+
+            let mut exited = false;
+            loop {
+                let event = match channel.next_output().await {
+                    Ok(Some(output)) => output,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = send(CommandEvent::Failed(e)).await;
+                        return;
+                    }
+                };
+                let event = match event {
+                    ssh::Output::Stdout(line) => CommandEvent::Stdout(line),
+                    ssh::Output::Stderr(line) => CommandEvent::Stderr(line),
+                    ssh::Output::Exit(code) => {
+                        exited = true;
+                        CommandEvent::Exited(code)
+                    }
+                };
+                if send(event).await.is_err() {
+                    return;
+                }
+            }
+
+            if !exited {
+                let _ = send(CommandEvent::Failed(Error::Exec(String::from(
+                    "Program did not exit cleanly !",
+                ))))
+                .await;
+            }
+        });
+
+        Self { handler }
+    }
+
+    /// Runs the drift checks on the host one after the other, sending the output of each.
+    ///
+    /// Checks exiting with a non-zero code fail with [`Error::Exit`], so that they are reported
+    /// apart from the hosts where they succeed.
+    pub fn drift(
+        sender: EventSender,
+        hostname: &str,
+        connection: ssh::Connection,
+        id: u64,
+        commands: Vec<String>,
+        timeout: Duration,
+    ) -> Self {
+        let _host = hostname.to_string();
+        let handler = tokio::spawn(async move {
+            let send = |event| sender.send(Event::DriftStatus(_host.to_string(), id, event));
+
+            for (check, command) in commands.iter().enumerate() {
+                let result = match try_open_channel(&connection, timeout).await {
+                    Ok(mut channel) => {
+                        channel.set_timeout(Some(timeout));
+                        channel.block_exec(command).await
+                    }
+                    Err(e) => Err(e),
+                };
+
+                let event = match result {
+                    Ok((0, stdout, _)) => DriftEvent::Output(check, stdout),
+                    Ok((code, _, stderr)) => DriftEvent::Failed(check, Error::Exit(code, stderr)),
+                    Err(e) => DriftEvent::Failed(check, e),
+                };
+                if send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { handler }
+    }
+
+    /// Stops the task of this handler.
+    pub fn abort(&self) {
+        self.handler.abort();
+    }
+
+    /// Stops the task of this handler, waiting for it to be dropped along with what it holds.
+    pub async fn stop(self) {
+        self.handler.abort();
+        // The task is either cancelled, finished or panicked, none of which matters anymore
+        let _ = self.handler.await;
+    }
+
+    /// Feeds recorded events back into the channel, honouring their original timing.
+    ///
+    /// The replay clock advances `speed` times faster than the wall clock and can be paused,
+    /// accelerated and moved around using [`ReplayCommand`]s.
+    pub fn replay(
+        sender: EventSender,
+        records: Vec<(Duration, Event)>,
+        speed: f64,
+        mut control: mpsc::UnboundedReceiver<ReplayCommand>,
+    ) -> Self {
+        let handler = tokio::spawn(async move {
+            let duration = records
+                .last()
+                .map(|(offset, _)| *offset)
+                .unwrap_or_default();
+            let mut speed = speed;
+            let mut position = Duration::ZERO;
+            let mut paused = false;
+            let mut next = 0;
+            let mut last = Instant::now();
+            let mut tick = interval(Duration::from_millis(50));
+            loop {
+                tokio::select! {
+                  _ = sender.closed() => {
+                    break;
+                  }
+                  _ = tick.tick() => {
+                    let now = Instant::now();
+                    if !paused {
+                      position = (position + now.duration_since(last).mul_f64(speed)).min(duration);
+                    }
+                    last = now;
+                  }
+                  Some(command) = control.recv() => {
+                    match command {
+                      ReplayCommand::TogglePause => paused = !paused,
+                      ReplayCommand::SeekForward(offset) => {
+                        position = (position + offset).min(duration);
+                      }
+                      ReplayCommand::SeekBackward(offset) => {
+                        position = position.saturating_sub(offset);
+                        next = 0;
+                        if sender.send(Event::Replay(ReplayEvent::Reset)).await.is_err() {
+                            return;
+                        }
+                      }
+                      ReplayCommand::Speed(factor) => {
+                        speed = (speed * factor).clamp(1.0 / 64.0, 1024.0);
+                      }
+                    }
+                  }
+                };
+
+                while let Some((_, event)) =
+                    records.get(next).filter(|(offset, _)| *offset <= position)
+                {
+                    if sender.send(event.clone()).await.is_err() {
+                        return;
+                    }
+                    next += 1;
+                }
+
+                let event = Event::Replay(ReplayEvent::Position {
+                    position,
+                    duration,
+                    speed,
+                    paused,
+                });
+                if sender.send(event).await.is_err() {
+                    return;
                 }
             }
         });
@@ -172,3 +905,83 @@ impl EventHandler {
         Self { handler }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(host: &str, load: &str) -> Event {
+        Event::LoadStatus(host.into(), LoadEvent::Load(load.into()))
+    }
+
+    /// Receives the events already sent, as the kind of every event and its payload.
+    async fn received(receiver: &mut EventReceiver) -> Vec<String> {
+        let mut events = vec![];
+        while let Ok(Some(event)) =
+            tokio::time::timeout(Duration::from_millis(10), receiver.recv()).await
+        {
+            events.push(match event {
+                Event::LoadStatus(host, LoadEvent::Load(load)) => format!("{} load {}", host, load),
+                Event::LoadStatus(host, LoadEvent::Latency(latency)) => {
+                    format!("{} latency {}", host, latency.as_millis())
+                }
+                Event::HostStatus(host, _) => format!("{} status", host),
+                Event::Replay(ReplayEvent::Reset) => String::from("reset"),
+                event => format!("{:?}", event),
+            });
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn coalesces_samples() {
+        let (sender, mut receiver) = channel(8);
+        sender.send(load("n1", "1")).await.unwrap();
+        sender.send(load("n1", "2")).await.unwrap();
+        sender.send(load("n2", "3")).await.unwrap();
+        assert_eq!(received(&mut receiver).await, ["n1 load 2", "n2 load 3"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_every_latency() {
+        let (sender, mut receiver) = channel(8);
+        for latency in [1, 2] {
+            let latency = LoadEvent::Latency(Duration::from_millis(latency));
+            sender
+                .send(Event::LoadStatus("n1".into(), latency))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            received(&mut receiver).await,
+            ["n1 latency 1", "n1 latency 2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_the_order_of_a_host() {
+        let (sender, mut receiver) = channel(8);
+        sender.send(load("n1", "1")).await.unwrap();
+        sender.send(load("n2", "2")).await.unwrap();
+        let status = Event::HostStatus("n1".into(), ConnectionEvent::Connecting);
+        sender.send(status).await.unwrap();
+        sender.send(load("n1", "3")).await.unwrap();
+
+        let events = received(&mut receiver).await;
+        let n1: Vec<&String> = events.iter().filter(|e| e.starts_with("n1")).collect();
+        assert_eq!(n1, ["n1 load 1", "n1 status", "n1 load 3"]);
+        assert!(events.contains(&String::from("n2 load 2")));
+    }
+
+    #[tokio::test]
+    async fn resets_drop_earlier_samples() {
+        let (sender, mut receiver) = channel(8);
+        sender.send(load("n1", "1")).await.unwrap();
+        sender
+            .send(Event::Replay(ReplayEvent::Reset))
+            .await
+            .unwrap();
+        sender.send(load("n1", "2")).await.unwrap();
+        assert_eq!(received(&mut receiver).await, ["reset", "n1 load 2"]);
+    }
+}
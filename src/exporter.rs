@@ -0,0 +1,198 @@
+use crate::app::{App, AppResult, Host, HostState, LoadAverage};
+use crate::error::Error;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+/// Largest request accepted before giving up on a client.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Time given to clients to send their request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Description of an exported gauge, and how to extract it from a host.
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&App, &Host) -> Option<f64>,
+}
+
+static GAUGES: &[Gauge] = &[
+    Gauge {
+        name: "jbtop_up",
+        help: "Whether the host is reachable",
+        value: |_, host| Some(matches!(host.state, HostState::Up(_)) as u8 as f64),
+    },
+    Gauge {
+        name: "jbtop_stale",
+        help: "Whether the host is up but did not report data recently",
+        value: |app, host| Some(app.is_stale(host) as u8 as f64),
+    },
+    Gauge {
+        name: "jbtop_data_age_seconds",
+        help: "Time since anything was last learnt about the host",
+        value: |_, host| Some(host.updated.elapsed().as_secs_f64()),
+    },
+    Gauge {
+        name: "jbtop_load1",
+        help: "1 minute load average",
+        value: |_, host| load(host).map(|load| load.one),
+    },
+    Gauge {
+        name: "jbtop_load5",
+        help: "5 minutes load average",
+        value: |_, host| load(host).map(|load| load.five),
+    },
+    Gauge {
+        name: "jbtop_load15",
+        help: "15 minutes load average",
+        value: |_, host| load(host).map(|load| load.fifteen),
+    },
+    Gauge {
+        name: "jbtop_processes_running",
+        help: "Number of runnable scheduling entities",
+        value: |_, host| load(host).map(|load| load.running as f64),
+    },
+    Gauge {
+        name: "jbtop_processes_total",
+        help: "Number of existing scheduling entities",
+        value: |_, host| load(host).map(|load| load.total as f64),
+    },
+    Gauge {
+        name: "jbtop_cpus",
+        help: "Number of processors available",
+        value: |_, host| host.cpus().map(f64::from),
+    },
+    Gauge {
+        name: "jbtop_memory_total_bytes",
+        help: "Total usable memory",
+        value: |_, host| memory(host).map(|(total, _)| total as f64 * 1024.0),
+    },
+    Gauge {
+        name: "jbtop_memory_available_bytes",
+        help: "Memory available for starting new applications",
+        value: |_, host| memory(host).map(|(_, available)| available as f64 * 1024.0),
+    },
+    Gauge {
+        name: "jbtop_sample_latency_seconds",
+        help: "Time taken by the last sample, from opening the channel to the exit of the command",
+        value: |_, host| host.latency.back().map(Duration::as_secs_f64),
+    },
+    Gauge {
+        name: "jbtop_handshake_seconds",
+        help: "Time taken to connect and authenticate the current session",
+        value: |_, host| host.handshake.map(|handshake| handshake.as_secs_f64()),
+    },
+];
+
+fn load(host: &Host) -> Option<LoadAverage> {
+    match &host.state {
+        HostState::Up(load) => load.parse().ok(),
+        _ => None,
+    }
+}
+
+fn memory(host: &Host) -> Option<(u64, u64)> {
+    match &host.state {
+        HostState::Up(_) => host.memory,
+        _ => None,
+    }
+}
+
+/// Renders the state of every host in the Prometheus text exposition format.
+pub fn render(app: &App) -> String {
+    let mut hosts: Vec<_> = app.hosts.iter().collect();
+    hosts.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut output = String::new();
+    for gauge in GAUGES {
+        let _ = writeln!(output, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(output, "# TYPE {} gauge", gauge.name);
+        for (name, host) in hosts.iter() {
+            if let Some(value) = (gauge.value)(app, host) {
+                let labels = labels(name, &host.group);
+                let _ = writeln!(output, "{}{{{}}} {}", gauge.name, labels, value);
+            }
+        }
+    }
+
+    output
+}
+
+/// Serves the rendered metrics of `app` over HTTP until an error occurs on the listener.
+pub async fn serve(addr: SocketAddr, app: Arc<Mutex<App>>) -> AppResult<()> {
+    let listener = TcpListener::bind(addr).await?;
+    log::info!("Serving metrics on http://{}/metrics", addr);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let app = Arc::clone(&app);
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, app).await {
+                log::warn!("Failed to answer {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Reads the request up to the end of its headers.
+async fn read_request(stream: &mut TcpStream) -> AppResult<Vec<u8>> {
+    let mut request = vec![];
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            return Err(Error::Parse(String::from("Incomplete request")));
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(request)
+}
+
+async fn respond(mut stream: TcpStream, app: Arc<Mutex<App>>) -> AppResult<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| Error::Timeout(REQUEST_TIMEOUT))??;
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(&*app.lock().await)),
+        (Some("GET"), _) => ("404 Not Found", String::from("Not Found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("Method Not Allowed\n"),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+fn labels(host: &str, group: &str) -> String {
+    format!(
+        "host=\"{}\",group=\"{}\"",
+        escape_label(host),
+        escape_label(group)
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,28 +1,300 @@
-use crate::app::{App, AppResult};
+use crate::app::{App, AppResult, PromptKind, View};
+use crate::command::{CommandStatus, OutputLine};
 use crate::event;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crate::keymap::{KeyAction, KeyContext};
+use crate::record::ReplayCommand;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Position;
+use std::time::Duration;
+
+/// Offset applied to the replay position by the seek keys.
+const SEEK_STEP: Duration = Duration::from_secs(10);
+
+/// Number of lines the log pane is scrolled by at once.
+const LOG_SCROLL_STEP: usize = 10;
+
+/// Number of rows or lines scrolled by a notch of the mouse wheel.
+const MOUSE_SCROLL_STEP: usize = 3;
 
 /// Handles the key events and updates the state of [`App`].
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
-    match key_event.code {
-        // Exit application on `ESC` or `q`
-        KeyCode::Esc | KeyCode::Char('q') => {
+    if app.prompt.is_some() {
+        return handle_prompt_key_events(key_event, app);
+    }
+
+    // Exit application on `Ctrl-C`, whatever the keymap
+    if let KeyCode::Char('c') | KeyCode::Char('C') = key_event.code {
+        if key_event.modifiers == KeyModifiers::CONTROL {
             app.quit();
+            return Ok(());
+        }
+    }
+
+    if app.help.is_some() {
+        return handle_help_key_events(key_event, app);
+    }
+
+    if app.popup.is_some() {
+        return handle_popup_key_events(key_event, app);
+    }
+
+    if app.command.is_some() {
+        return handle_command_key_events(key_event, app);
+    }
+
+    if app.drift.is_some() {
+        return handle_drift_key_events(key_event, app);
+    }
+
+    // Switch to a tab by its number whatever the keymap, other digits can still be bound
+    if let KeyCode::Char(digit @ '1'..='9') = key_event.code {
+        let view = View::ALL.get(digit as usize - '1' as usize);
+        if let (Some(view), KeyModifiers::NONE) = (view, key_event.modifiers) {
+            app.view = *view;
+            return Ok(());
+        }
+    }
+
+    let Some(action) = app.keymap.get(&key_event, KeyContext::Main) else {
+        return Ok(());
+    };
+
+    match action {
+        KeyAction::Quit => app.quit(),
+        KeyAction::Help => app.toggle_help(),
+        KeyAction::NextView => app.view = app.view.cycle(true),
+        KeyAction::PreviousView => app.view = app.view.cycle(false),
+        // Replay handlers
+        KeyAction::ReplayPause => app.control_replay(ReplayCommand::TogglePause),
+        KeyAction::SeekForward => app.control_replay(ReplayCommand::SeekForward(SEEK_STEP)),
+        KeyAction::SeekBackward => app.control_replay(ReplayCommand::SeekBackward(SEEK_STEP)),
+        KeyAction::ReplayFaster => app.control_replay(ReplayCommand::Speed(2.0)),
+        KeyAction::ReplaySlower => app.control_replay(ReplayCommand::Speed(0.5)),
+        action => match app.view {
+            View::Hosts => handle_hosts_action(action, app),
+            View::Alerts => handle_scroll_action(action, &mut app.alerts.scroll),
+            View::Log => handle_log_action(action, app),
+            View::Overview | View::Lustre => {}
+        },
+    }
+
+    Ok(())
+}
+
+/// Handles the actions of the keys pressed in the hosts tab.
+fn handle_hosts_action(action: KeyAction, app: &mut App) {
+    match action {
+        // Host table handlers
+        KeyAction::SelectNext => app.select_next(),
+        KeyAction::SelectPrevious => app.select_previous(),
+        KeyAction::SelectFirst => app.select_first(),
+        KeyAction::SelectLast => app.select_last(),
+        KeyAction::ToggleMark => app.toggle_mark(),
+        KeyAction::FoldErrors => app.toggle_fold_errors(),
+        KeyAction::ErrorFilter => app.cycle_error_filter(),
+        KeyAction::Sort => app.cycle_sort(),
+        KeyAction::ReverseSort => app.reverse_sort(),
+        KeyAction::Prompt => app.open_prompt(PromptKind::Command),
+        KeyAction::AddHosts => app.open_prompt(PromptKind::AddHosts),
+        KeyAction::RemoveHosts => app.open_prompt(PromptKind::RemoveHosts),
+        KeyAction::Alerts => app.toggle_alerts(),
+        KeyAction::Drift => app.start_drift(),
+        // Inventory handlers
+        KeyAction::GroupBy => app.cycle_group_by(),
+        KeyAction::Open => app.open_selected(),
+        KeyAction::ClearFilter => app.clear_fact_filter(),
+        // Log pane handlers
+        KeyAction::Log => app.toggle_log(),
+        KeyAction::LogHosts => app.toggle_log_hosts(),
+        KeyAction::LogScrollUp => app.history.scroll_up(LOG_SCROLL_STEP),
+        KeyAction::LogScrollDown => app.history.scroll_down(LOG_SCROLL_STEP),
+        _ => {}
+    }
+}
+
+/// Handles the actions of the keys pressed in the alerts tab and the overlays, where the row keys
+/// scroll.
+fn handle_scroll_action(action: KeyAction, scroll: &mut u16) {
+    match action {
+        KeyAction::SelectNext => *scroll = scroll.saturating_add(1),
+        KeyAction::SelectPrevious => *scroll = scroll.saturating_sub(1),
+        KeyAction::LogScrollDown => *scroll = scroll.saturating_add(LOG_SCROLL_STEP as u16),
+        KeyAction::LogScrollUp => *scroll = scroll.saturating_sub(LOG_SCROLL_STEP as u16),
+        KeyAction::SelectFirst => *scroll = 0,
+        _ => {}
+    }
+}
+
+/// Handles the actions of the keys pressed in the log tab, where the row keys scroll.
+fn handle_log_action(action: KeyAction, app: &mut App) {
+    match action {
+        KeyAction::SelectNext => app.history.scroll_down(1),
+        KeyAction::SelectPrevious => app.history.scroll_up(1),
+        KeyAction::LogScrollDown => app.history.scroll_down(LOG_SCROLL_STEP),
+        KeyAction::LogScrollUp => app.history.scroll_up(LOG_SCROLL_STEP),
+        _ => {}
+    }
+}
+
+/// Handles the mouse events: clicks select rows and sort by columns, the wheel scrolls the widget
+/// under the pointer.
+pub fn handle_mouse_events(mouse_event: MouseEvent, app: &mut App) -> AppResult<()> {
+    if app.prompt.is_some() {
+        return Ok(());
+    }
+
+    if app.help.is_some() || app.popup.is_some() {
+        if let MouseEventKind::Down(MouseButton::Left) = mouse_event.kind {
+            app.help = None;
+            app.close_popup();
+        }
+        return Ok(());
+    }
+
+    let position = Position::new(mouse_event.column, mouse_event.row);
+    let table = app.areas.table;
+    let in_results = app.areas.results.contains(position);
+    let in_log = app.areas.log.contains(position);
+    let in_alerts = app.view == View::Alerts && app.areas.view.contains(position);
+    let tab = app
+        .areas
+        .tabs
+        .iter()
+        .find(|(title, _)| title.contains(position))
+        .map(|(_, view)| *view);
+
+    match mouse_event.kind {
+        MouseEventKind::Down(MouseButton::Left) if tab.is_some() => {
+            if let Some(view) = tab {
+                app.view = view;
+            }
         }
-        // Exit application on `Ctrl-C`
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.quit();
+        MouseEventKind::Down(MouseButton::Left) if table.contains(position) => {
+            if position.y == table.y {
+                let column = app
+                    .areas
+                    .header
+                    .iter()
+                    .find(|(cell, _)| cell.contains(position))
+                    .and_then(|(_, column)| *column);
+                if let Some(column) = column {
+                    app.sort_by(column);
+                }
+            } else {
+                let row = app.table_state.offset() + (position.y - table.y - 1) as usize;
+                app.click_row(row);
             }
         }
-        // Counter handlers
-        KeyCode::Right => {
-            app.increment_counter();
+        MouseEventKind::ScrollDown if in_results => scroll_results(app, MOUSE_SCROLL_STEP as i16),
+        MouseEventKind::ScrollUp if in_results => scroll_results(app, -(MOUSE_SCROLL_STEP as i16)),
+        MouseEventKind::ScrollDown if in_log => app.history.scroll_down(MOUSE_SCROLL_STEP),
+        MouseEventKind::ScrollUp if in_log => app.history.scroll_up(MOUSE_SCROLL_STEP),
+        MouseEventKind::ScrollDown if in_alerts => {
+            app.alerts.scroll = app.alerts.scroll.saturating_add(MOUSE_SCROLL_STEP as u16)
         }
-        KeyCode::Left => {
-            app.decrement_counter();
+        MouseEventKind::ScrollUp if in_alerts => {
+            app.alerts.scroll = app.alerts.scroll.saturating_sub(MOUSE_SCROLL_STEP as u16)
         }
-        // Other handlers you could add here.
+        MouseEventKind::ScrollDown if table.contains(position) => {
+            (0..MOUSE_SCROLL_STEP).for_each(|_| app.select_next())
+        }
+        MouseEventKind::ScrollUp if table.contains(position) => {
+            (0..MOUSE_SCROLL_STEP).for_each(|_| app.select_previous())
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Scrolls the results of the command or of the drift checks by the given number of lines.
+fn scroll_results(app: &mut App, lines: i16) {
+    let scroll = match (app.command.as_mut(), app.drift.as_mut()) {
+        (Some(run), _) => &mut run.scroll,
+        (None, Some(run)) => &mut run.scroll,
+        (None, None) => return,
+    };
+    *scroll = scroll.saturating_add_signed(lines);
+}
+
+/// Handles the key events while the prompt is open.
+fn handle_prompt_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    let Some(prompt) = app.prompt.as_mut() else {
+        return Ok(());
+    };
+
+    match key_event.code {
+        KeyCode::Esc => app.close_prompt(),
+        KeyCode::Char('c') if key_event.modifiers == KeyModifiers::CONTROL => app.close_prompt(),
+        KeyCode::Enter => app.submit_prompt(),
+        KeyCode::Backspace => {
+            prompt.text.pop();
+        }
+        KeyCode::Char(c) => prompt.text.push(c),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Handles the key events while the help is displayed.
+fn handle_help_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    let action = app.keymap.get(&key_event, KeyContext::Main);
+    let Some(scroll) = app.help.as_mut() else {
+        return Ok(());
+    };
+
+    match (key_event.code, action) {
+        (KeyCode::Esc, _) | (_, Some(KeyAction::Quit | KeyAction::Help)) => app.help = None,
+        (_, Some(action)) => handle_scroll_action(action, scroll),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Handles the key events while the details of a host are displayed.
+fn handle_popup_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    let action = app.keymap.get(&key_event, KeyContext::Main);
+    match (key_event.code, action) {
+        (KeyCode::Esc, _) | (_, Some(KeyAction::Quit | KeyAction::Open)) => app.close_popup(),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Handles the key events while command results are displayed.
+fn handle_command_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    let action = app.keymap.get(&key_event, KeyContext::Results);
+    let Some(run) = app.command.as_mut() else {
+        return Ok(());
+    };
+
+    match (key_event.code, action) {
+        (KeyCode::Esc, _) | (_, Some(KeyAction::Quit)) => app.close_command(),
+        (_, Some(KeyAction::ToggleDiff)) => run.show_diff = !run.show_diff,
+        (_, Some(KeyAction::ToggleGrouping)) => run.grouped = !run.grouped,
+        (_, Some(KeyAction::Prompt)) => app.open_prompt(PromptKind::Command),
+        (_, Some(action)) => handle_scroll_action(action, &mut run.scroll),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Handles the key events while the drift report is displayed.
+fn handle_drift_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    let action = app.keymap.get(&key_event, KeyContext::Results);
+    let Some(run) = app.drift.as_mut() else {
+        return Ok(());
+    };
+
+    match (key_event.code, action) {
+        (KeyCode::Esc, _) | (_, Some(KeyAction::Quit)) => app.close_drift(),
+        (_, Some(KeyAction::Rerun)) => app.start_drift(),
+        (_, Some(KeyAction::Prompt)) => app.open_prompt(PromptKind::Command),
+        (_, Some(action)) => handle_scroll_action(action, &mut run.scroll),
         _ => {}
     }
 
@@ -34,18 +306,91 @@ pub fn handle_host_events(
     event: event::ConnectionEvent,
     app: &mut App,
 ) -> AppResult<()> {
+    // Events sent by the tasks of a removed host before they were stopped
+    if app.removed.contains(host) {
+        return Ok(());
+    }
+
     match event {
-        event::ConnectionEvent::Connected => {}
+        event::ConnectionEvent::Queued => app.set_host_queued(host),
         event::ConnectionEvent::Connecting => app.set_host_connecting(host),
-        event::ConnectionEvent::ConnectionError(error) => app.set_host_error(host, &error),
+        event::ConnectionEvent::Authenticating => app.set_host_authenticating(host),
+        event::ConnectionEvent::Connected(handshake) => app.set_host_connected(host, handshake),
+        event::ConnectionEvent::Backoff(delay, error) => app.set_host_backoff(host, delay, error),
+        event::ConnectionEvent::Failed(error) => app.set_host_error(host, error),
     }
 
     Ok(())
 }
+
 pub fn handle_load_events(host: &str, event: event::LoadEvent, app: &mut App) -> AppResult<()> {
+    if app.removed.contains(host) {
+        return Ok(());
+    }
+
     match event {
         event::LoadEvent::Load(status) => app.set_host_status(host, &status),
-        event::LoadEvent::LoadError(error) => app.set_host_error(host, &error),
+        event::LoadEvent::Latency(latency) => app.push_host_latency(host, latency),
+        event::LoadEvent::Memory(total, available) => app.set_host_memory(host, total, available),
+        event::LoadEvent::Inventory(inventory) => app.set_host_inventory(host, *inventory),
+        event::LoadEvent::Lustre(mounts, health) => app.set_host_lustre(host, mounts, health),
+        event::LoadEvent::LoadError(error) => app.set_host_degraded(host, error),
+    }
+
+    Ok(())
+}
+
+pub fn handle_replay_events(event: event::ReplayEvent, app: &mut App) -> AppResult<()> {
+    match event {
+        event::ReplayEvent::Reset => app.clear_hosts(),
+        event::ReplayEvent::Position {
+            position,
+            duration,
+            speed,
+            paused,
+        } => app.set_replay_position(position, duration, speed, paused),
+    }
+
+    Ok(())
+}
+
+pub fn handle_command_events(
+    host: &str,
+    id: u64,
+    event: event::CommandEvent,
+    app: &mut App,
+) -> AppResult<()> {
+    match event {
+        event::CommandEvent::Stdout(line) => {
+            app.push_command_output(host, id, OutputLine::Stdout(line))
+        }
+        event::CommandEvent::Stderr(line) => {
+            app.push_command_output(host, id, OutputLine::Stderr(line))
+        }
+        event::CommandEvent::Exited(code) => {
+            app.set_command_status(host, id, CommandStatus::Exited(code))
+        }
+        event::CommandEvent::Failed(error) => {
+            app.set_command_status(host, id, CommandStatus::Failed(error))
+        }
+    }
+
+    Ok(())
+}
+
+pub fn handle_drift_events(
+    host: &str,
+    id: u64,
+    event: event::DriftEvent,
+    app: &mut App,
+) -> AppResult<()> {
+    match event {
+        event::DriftEvent::Output(check, output) => {
+            app.set_drift_result(host, id, check, Ok(output))
+        }
+        event::DriftEvent::Failed(check, error) => {
+            app.set_drift_result(host, id, check, Err(error))
+        }
     }
 
     Ok(())
@@ -0,0 +1,146 @@
+use crate::event::{Event, EventSender};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::collections::{HashSet, VecDeque};
+use std::time::SystemTime;
+
+/// Number of entries kept in the log, older entries are dropped.
+const MAX_ENTRIES: usize = 10000;
+
+/// A timestamped line of the log.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub time: SystemTime,
+    pub level: Level,
+    /// Host the entry is about, `None` for messages logged by the application.
+    pub host: Option<String>,
+    pub message: String,
+}
+
+impl Entry {
+    pub fn new(level: Level, host: Option<&str>, message: String) -> Self {
+        Self {
+            time: SystemTime::now(),
+            level,
+            host: host.map(str::to_string),
+            message,
+        }
+    }
+}
+
+/// Log of the events of the hosts and of the messages of the application, along with the state
+/// of the pane showing it.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    /// Whether the log pane is shown.
+    pub visible: bool,
+    /// Number of lines scrolled back from the latest entry.
+    pub scroll: usize,
+    /// Only show the entries of these hosts.
+    pub hosts: Option<HashSet<String>>,
+}
+
+impl History {
+    pub fn push(&mut self, entry: Entry) {
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Forgets the entries of the hosts, keeping the messages of the application.
+    pub fn clear_hosts(&mut self) {
+        self.entries.retain(|entry| entry.host.is_none());
+    }
+
+    /// Entries shown in the pane, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| match (&self.hosts, &entry.host) {
+                (Some(hosts), Some(host)) => hosts.contains(host),
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+    }
+
+    /// Scrolls back, at most until the oldest entry shown is the only one left.
+    pub fn scroll_up(&mut self, lines: usize) {
+        let last = self.entries().count().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add(lines).min(last);
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+}
+
+/// Logger sending the records of the `log` crate to the event channel, so that they end up in the
+/// log pane instead of being written over the interface.
+pub struct Logger {
+    sender: EventSender,
+    level: LevelFilter,
+    /// Levels overriding the default one for the targets starting with the given module paths.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Logger {
+    pub fn new(sender: EventSender) -> Self {
+        Self {
+            sender,
+            level: LevelFilter::Trace,
+            modules: vec![],
+        }
+    }
+
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_module_level(mut self, module: &str, level: LevelFilter) -> Self {
+        self.modules.push((module.to_string(), level));
+        self
+    }
+
+    /// Installs the logger as the logger of the `log` crate.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let max = self
+            .modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, Ord::max);
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max);
+        Ok(())
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| target.starts_with(module.as_str()))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Records are dropped rather than waited for when the interface falls behind, as they can
+        // be logged from anywhere
+        let message = format!("[{}] {}", record.target(), record.args());
+        let _ = self
+            .sender
+            .try_send(Event::Log(Entry::new(record.level(), None, message)));
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,220 @@
+use std::time::{Duration, SystemTime};
+
+/// Commands run once on every host after connecting, printing a `key=value` line per fact.
+pub static SCRIPT: &str = "\
+echo \"kernel=$(uname -r)\"; \
+(. /etc/os-release && echo \"os=$PRETTY_NAME\"); \
+echo \"cpu_model=$(grep -m1 -E '^(model name|Model)' /proc/cpuinfo | cut -d: -f2- | sed 's/^ *//')\"; \
+echo \"cpus=$(nproc)\"; \
+echo \"cores=$(awk -F: '/^physical id/ {p=$2} /^core id/ {print p\":\"$2}' /proc/cpuinfo | sort -u | wc -l)\"; \
+echo \"sockets=$(grep '^physical id' /proc/cpuinfo | sort -u | wc -l)\"; \
+echo \"memory=$(awk '/^MemTotal:/ {print $2}' /proc/meminfo)\"; \
+echo \"uptime=$(cut -d' ' -f1 /proc/uptime)\"; \
+echo \"boot=$(awk '/^btime/ {print $2}' /proc/stat)\"; \
+echo \"lustre=$(awk '$3 == \"lustre\" {print $1}' /proc/mounts | sort -u | paste -sd' ')\"; \
+echo \"lustre_health=$(lctl get_param -n health_check 2>/dev/null | head -1)\"";
+
+/// Lustre facts of [`SCRIPT`], sampled again while the host is up since filesystems can be
+/// mounted, unmounted or become unhealthy at any time.
+pub static LUSTRE_SCRIPT: &str = "\
+echo \"lustre=$(awk '$3 == \"lustre\" {print $1}' /proc/mounts | sort -u | paste -sd' ')\"; \
+echo \"lustre_health=$(lctl get_param -n health_check 2>/dev/null | head -1)\"";
+
+/// Facts hosts can be grouped by, in the order they are cycled through.
+pub const GROUP_FIELDS: [&str; 8] = [
+    "kernel",
+    "os",
+    "cpu_model",
+    "cpus",
+    "cores",
+    "sockets",
+    "memory",
+    "lustre",
+];
+
+/// Facts about a host that do not change while it is up.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Inventory {
+    pub kernel: Option<String>,
+    /// Pretty name of the distribution.
+    pub os: Option<String>,
+    pub cpu_model: Option<String>,
+    /// Number of processors available.
+    pub cpus: Option<u32>,
+    /// Number of physical cores.
+    pub cores: Option<u32>,
+    pub sockets: Option<u32>,
+    /// Total memory, in kB.
+    pub memory: Option<u64>,
+    /// Time the host had been up for when the inventory was collected.
+    pub uptime: Option<Duration>,
+    /// Time the host booted at, in seconds since the epoch.
+    pub boot: Option<u64>,
+    /// Lustre filesystems mounted, as `nid:/fsname` sources separated by spaces.
+    pub lustre: Option<String>,
+    /// Health reported by the Lustre modules, `healthy` when all is well.
+    pub lustre_health: Option<String>,
+}
+
+impl Inventory {
+    /// Reads the output of [`SCRIPT`]. Facts that are missing or empty are left unknown.
+    pub fn parse(output: &str) -> Self {
+        let mut inventory = Self::default();
+        for line in output.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            let text = || Some(value.to_string());
+            // Counts of 0 mean the kernel does not expose the information
+            let count = || value.parse().ok().filter(|count| *count > 0);
+            match key {
+                "kernel" => inventory.kernel = text(),
+                "os" => inventory.os = text(),
+                "cpu_model" => inventory.cpu_model = text(),
+                "cpus" => inventory.cpus = count(),
+                "cores" => inventory.cores = count(),
+                "sockets" => inventory.sockets = count(),
+                "memory" => inventory.memory = value.parse().ok(),
+                "uptime" => {
+                    inventory.uptime = value
+                        .parse()
+                        .ok()
+                        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                }
+                "boot" => inventory.boot = value.parse().ok(),
+                "lustre" => inventory.lustre = text(),
+                "lustre_health" => inventory.lustre_health = text(),
+                _ => (),
+            }
+        }
+        inventory
+    }
+
+    /// Known facts as `key=value` lines, the way [`Inventory::parse`] reads them.
+    pub fn encode(&self) -> String {
+        self.fields()
+            .into_iter()
+            .filter_map(|(key, value)| Some(format!("{}={}", key, value?)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Every fact, in the order they are displayed.
+    pub fn fields(&self) -> Vec<(&'static str, Option<String>)> {
+        let string = |value: &Option<String>| value.clone();
+        let number = |value: Option<u32>| value.map(|value| value.to_string());
+        vec![
+            ("kernel", string(&self.kernel)),
+            ("os", string(&self.os)),
+            ("cpu_model", string(&self.cpu_model)),
+            ("cpus", number(self.cpus)),
+            ("cores", number(self.cores)),
+            ("sockets", number(self.sockets)),
+            ("memory", self.memory.map(|memory| memory.to_string())),
+            (
+                "uptime",
+                self.uptime
+                    .map(|uptime| format!("{:.0}", uptime.as_secs_f64())),
+            ),
+            ("boot", self.boot.map(|boot| boot.to_string())),
+            ("lustre", string(&self.lustre)),
+            ("lustre_health", string(&self.lustre_health)),
+        ]
+    }
+
+    /// Value of a fact, as given by [`Inventory::fields`].
+    pub fn get(&self, field: &str) -> Option<String> {
+        self.fields()
+            .into_iter()
+            .find(|(key, _)| *key == field)
+            .and_then(|(_, value)| value)
+    }
+
+    /// Lustre filesystems mounted on the host.
+    pub fn lustre_mounts(&self) -> impl Iterator<Item = &str> {
+        self.lustre
+            .iter()
+            .flat_map(|mounts| mounts.split_whitespace())
+    }
+
+    /// Time since the host booted.
+    pub fn up_for(&self) -> Option<Duration> {
+        let boot = SystemTime::UNIX_EPOCH + Duration::from_secs(self.boot?);
+        SystemTime::now().duration_since(boot).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_facts() {
+        let inventory = Inventory::parse(
+            "kernel=6.1.0-18-amd64\n\
+             os=Debian GNU/Linux 12 (bookworm)\n\
+             cpu_model=AMD EPYC 7763 64-Core Processor\n\
+             cpus=128\n\
+             cores=64\n\
+             sockets=1\n\
+             memory=263846524\n\
+             uptime=1234.56\n\
+             boot=1700000000\n\
+             lustre=10.0.0.1@o2ib:/scratch 10.0.0.2@o2ib:/home\n\
+             lustre_health=healthy",
+        );
+        assert_eq!(inventory.kernel.as_deref(), Some("6.1.0-18-amd64"));
+        assert_eq!(
+            inventory.os.as_deref(),
+            Some("Debian GNU/Linux 12 (bookworm)")
+        );
+        assert_eq!(inventory.cpus, Some(128));
+        assert_eq!(inventory.cores, Some(64));
+        assert_eq!(inventory.sockets, Some(1));
+        assert_eq!(inventory.memory, Some(263846524));
+        assert_eq!(inventory.uptime, Some(Duration::from_secs_f64(1234.56)));
+        assert_eq!(inventory.boot, Some(1700000000));
+        assert_eq!(
+            inventory.lustre_mounts().collect::<Vec<_>>(),
+            ["10.0.0.1@o2ib:/scratch", "10.0.0.2@o2ib:/home"]
+        );
+        assert_eq!(inventory.lustre_health.as_deref(), Some("healthy"));
+    }
+
+    #[test]
+    fn leaves_missing_facts_unknown() {
+        let inventory = Inventory::parse(
+            "kernel=\n\
+             cores=0\n\
+             sockets=0\n\
+             cpus=many\n\
+             lustre=\n\
+             garbage\n\
+             unknown=value\n\
+             os= Rocky Linux 9.3 ",
+        );
+        assert_eq!(
+            inventory,
+            Inventory {
+                os: Some(String::from("Rocky Linux 9.3")),
+                ..Inventory::default()
+            }
+        );
+        assert_eq!(inventory.lustre_mounts().count(), 0);
+    }
+
+    #[test]
+    fn parses_what_is_encoded() {
+        let inventory =
+            Inventory::parse("kernel=5.14\ncpus=8\nuptime=60\nlustre_health=NOT HEALTHY");
+        assert_eq!(Inventory::parse(&inventory.encode()), inventory);
+        assert_eq!(inventory.get("cpus").as_deref(), Some("8"));
+        assert_eq!(inventory.get("uptime").as_deref(), Some("60"));
+        assert_eq!(inventory.get("memory"), None);
+    }
+}
@@ -0,0 +1,560 @@
+use crate::app::AppResult;
+use crate::config::KeymapConfig;
+use crate::error::Error;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Where the keys bound to an action apply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyContext {
+    /// Everywhere but the prompt, unless the key is bound to an action of a more specific context.
+    Main,
+    /// The results of a command and the drift report.
+    Results,
+}
+
+/// Something a key can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyAction {
+    Quit,
+    Help,
+    NextView,
+    PreviousView,
+    SelectNext,
+    SelectPrevious,
+    SelectFirst,
+    SelectLast,
+    ToggleMark,
+    Open,
+    Prompt,
+    AddHosts,
+    RemoveHosts,
+    Drift,
+    FoldErrors,
+    ErrorFilter,
+    GroupBy,
+    ClearFilter,
+    Sort,
+    ReverseSort,
+    Alerts,
+    Log,
+    LogHosts,
+    LogScrollUp,
+    LogScrollDown,
+    ReplayPause,
+    SeekForward,
+    SeekBackward,
+    ReplayFaster,
+    ReplaySlower,
+    ToggleDiff,
+    ToggleGrouping,
+    Rerun,
+}
+
+impl KeyAction {
+    /// Every action, in the order they are listed in the help.
+    pub const ALL: [KeyAction; 33] = [
+        KeyAction::Quit,
+        KeyAction::Help,
+        KeyAction::NextView,
+        KeyAction::PreviousView,
+        KeyAction::SelectNext,
+        KeyAction::SelectPrevious,
+        KeyAction::SelectFirst,
+        KeyAction::SelectLast,
+        KeyAction::ToggleMark,
+        KeyAction::Open,
+        KeyAction::Prompt,
+        KeyAction::AddHosts,
+        KeyAction::RemoveHosts,
+        KeyAction::Drift,
+        KeyAction::FoldErrors,
+        KeyAction::ErrorFilter,
+        KeyAction::GroupBy,
+        KeyAction::ClearFilter,
+        KeyAction::Sort,
+        KeyAction::ReverseSort,
+        KeyAction::Alerts,
+        KeyAction::Log,
+        KeyAction::LogHosts,
+        KeyAction::LogScrollUp,
+        KeyAction::LogScrollDown,
+        KeyAction::ReplayPause,
+        KeyAction::SeekForward,
+        KeyAction::SeekBackward,
+        KeyAction::ReplayFaster,
+        KeyAction::ReplaySlower,
+        KeyAction::ToggleDiff,
+        KeyAction::ToggleGrouping,
+        KeyAction::Rerun,
+    ];
+
+    /// Name of the action in the configuration file.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "quit",
+            KeyAction::Help => "help",
+            KeyAction::NextView => "next-view",
+            KeyAction::PreviousView => "previous-view",
+            KeyAction::SelectNext => "select-next",
+            KeyAction::SelectPrevious => "select-previous",
+            KeyAction::SelectFirst => "select-first",
+            KeyAction::SelectLast => "select-last",
+            KeyAction::ToggleMark => "toggle-mark",
+            KeyAction::Open => "open",
+            KeyAction::Prompt => "prompt",
+            KeyAction::AddHosts => "add-hosts",
+            KeyAction::RemoveHosts => "remove-hosts",
+            KeyAction::Drift => "drift",
+            KeyAction::FoldErrors => "fold-errors",
+            KeyAction::ErrorFilter => "error-filter",
+            KeyAction::GroupBy => "group-by",
+            KeyAction::ClearFilter => "clear-filter",
+            KeyAction::Sort => "sort",
+            KeyAction::ReverseSort => "reverse-sort",
+            KeyAction::Alerts => "alerts",
+            KeyAction::Log => "log",
+            KeyAction::LogHosts => "log-hosts",
+            KeyAction::LogScrollUp => "log-scroll-up",
+            KeyAction::LogScrollDown => "log-scroll-down",
+            KeyAction::ReplayPause => "replay-pause",
+            KeyAction::SeekForward => "seek-forward",
+            KeyAction::SeekBackward => "seek-backward",
+            KeyAction::ReplayFaster => "replay-faster",
+            KeyAction::ReplaySlower => "replay-slower",
+            KeyAction::ToggleDiff => "toggle-diff",
+            KeyAction::ToggleGrouping => "toggle-grouping",
+            KeyAction::Rerun => "rerun",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Line describing the action in the help.
+    pub fn description(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "Quit",
+            KeyAction::Help => "Show this help",
+            KeyAction::NextView => "Switch to the next tab",
+            KeyAction::PreviousView => "Switch to the previous tab",
+            KeyAction::SelectNext => "Highlight the next row",
+            KeyAction::SelectPrevious => "Highlight the previous row",
+            KeyAction::SelectFirst => "Highlight the first row",
+            KeyAction::SelectLast => "Highlight the last row",
+            KeyAction::ToggleMark => "Mark the hosts of the highlighted row",
+            KeyAction::Open => "Show the details of the highlighted host, or only its group",
+            KeyAction::Prompt => "Run a command on the marked hosts",
+            KeyAction::AddHosts => "Monitor the hosts of a nodeset",
+            KeyAction::RemoveHosts => "Stop monitoring a nodeset, or the marked or listed hosts",
+            KeyAction::Drift => "Compare the configuration of the marked hosts",
+            KeyAction::FoldErrors => "Fold the hosts failing with the same error",
+            KeyAction::ErrorFilter => "Only show the next class of errors",
+            KeyAction::GroupBy => "Group the hosts by the next fact of their inventory",
+            KeyAction::ClearFilter => "Show the hosts of every group",
+            KeyAction::Sort => "Sort the hosts by the next column",
+            KeyAction::ReverseSort => "Reverse the order of the hosts",
+            KeyAction::Alerts => "Show the alerts panel",
+            KeyAction::Log => "Show the log pane",
+            KeyAction::LogHosts => "Only log the hosts of the highlighted row",
+            KeyAction::LogScrollUp => "Scroll the log back",
+            KeyAction::LogScrollDown => "Scroll the log forward",
+            KeyAction::ReplayPause => "Pause or resume the replay",
+            KeyAction::SeekForward => "Skip forward in the replay",
+            KeyAction::SeekBackward => "Skip backward in the replay",
+            KeyAction::ReplayFaster => "Replay twice as fast",
+            KeyAction::ReplaySlower => "Replay twice as slow",
+            KeyAction::ToggleDiff => "Show the differences with the most common output",
+            KeyAction::ToggleGrouping => "Group the hosts with identical output",
+            KeyAction::Rerun => "Run the drift checks again",
+        }
+    }
+
+    /// Where the keys bound to the action apply.
+    pub fn context(self) -> KeyContext {
+        match self {
+            KeyAction::ToggleDiff | KeyAction::ToggleGrouping | KeyAction::Rerun => {
+                KeyContext::Results
+            }
+            _ => KeyContext::Main,
+        }
+    }
+}
+
+/// Bindings shared by every preset.
+static DEFAULT_BINDINGS: &[(&str, KeyAction)] = &[
+    ("q", KeyAction::Quit),
+    ("esc", KeyAction::Quit),
+    ("?", KeyAction::Help),
+    ("tab", KeyAction::NextView),
+    ("backtab", KeyAction::PreviousView),
+    ("down", KeyAction::SelectNext),
+    ("j", KeyAction::SelectNext),
+    ("up", KeyAction::SelectPrevious),
+    ("k", KeyAction::SelectPrevious),
+    ("home", KeyAction::SelectFirst),
+    ("end", KeyAction::SelectLast),
+    ("space", KeyAction::ToggleMark),
+    ("enter", KeyAction::Open),
+    (":", KeyAction::Prompt),
+    ("n", KeyAction::AddHosts),
+    ("x", KeyAction::RemoveHosts),
+    ("d", KeyAction::Drift),
+    ("f", KeyAction::FoldErrors),
+    ("e", KeyAction::ErrorFilter),
+    ("i", KeyAction::GroupBy),
+    ("backspace", KeyAction::ClearFilter),
+    ("s", KeyAction::Sort),
+    ("r", KeyAction::ReverseSort),
+    ("a", KeyAction::Alerts),
+    ("l", KeyAction::Log),
+    ("h", KeyAction::LogHosts),
+    ("pageup", KeyAction::LogScrollUp),
+    ("pagedown", KeyAction::LogScrollDown),
+    ("p", KeyAction::ReplayPause),
+    ("right", KeyAction::SeekForward),
+    ("left", KeyAction::SeekBackward),
+    ("+", KeyAction::ReplayFaster),
+    ("-", KeyAction::ReplaySlower),
+    ("d", KeyAction::ToggleDiff),
+    ("g", KeyAction::ToggleGrouping),
+    ("r", KeyAction::Rerun),
+];
+
+/// Bindings added by the `vim` preset.
+static VIM_BINDINGS: &[(&str, KeyAction)] = &[
+    ("g", KeyAction::SelectFirst),
+    ("G", KeyAction::SelectLast),
+    ("ctrl-u", KeyAction::LogScrollUp),
+    ("ctrl-b", KeyAction::LogScrollUp),
+    ("ctrl-d", KeyAction::LogScrollDown),
+    ("ctrl-f", KeyAction::LogScrollDown),
+];
+
+/// Bindings added by the `htop` preset.
+static HTOP_BINDINGS: &[(&str, KeyAction)] = &[
+    ("f1", KeyAction::Help),
+    ("f10", KeyAction::Quit),
+    ("f4", KeyAction::ErrorFilter),
+    ("f5", KeyAction::GroupBy),
+    ("f6", KeyAction::Sort),
+    ("I", KeyAction::ReverseSort),
+];
+
+/// Names of the presets, along with the bindings they add to the default ones.
+pub static PRESETS: &[(&str, &[(&str, KeyAction)])] = &[
+    ("default", &[]),
+    ("vim", VIM_BINDINGS),
+    ("htop", HTOP_BINDINGS),
+];
+
+/// A key, along with the control and alt modifiers held with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Binding matching the key event. Shift is ignored for characters and back tab, as it is part
+    /// of the key itself.
+    pub fn from_event(event: &KeyEvent) -> Self {
+        let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers |= event.modifiers & KeyModifiers::SHIFT;
+        }
+        Self {
+            code: event.code,
+            modifiers,
+        }
+    }
+
+    /// Reads a key such as `q`, `G`, `ctrl-d`, `pageup` or `f10`.
+    pub fn parse(text: &str) -> AppResult<Self> {
+        let invalid = || Error::Parse(format!("Invalid key: {}", text));
+
+        let mut modifiers = KeyModifiers::NONE;
+        let mut key = text;
+        loop {
+            if let Some(rest) = key.strip_prefix("ctrl-").filter(|rest| !rest.is_empty()) {
+                modifiers |= KeyModifiers::CONTROL;
+                key = rest;
+            } else if let Some(rest) = key.strip_prefix("alt-").filter(|rest| !rest.is_empty()) {
+                modifiers |= KeyModifiers::ALT;
+                key = rest;
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "esc" => KeyCode::Esc,
+                "enter" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').map(str::parse) {
+                    Some(Ok(number @ 1..=12)) => KeyCode::F(number),
+                    _ => return Err(invalid()),
+                },
+            },
+        };
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(number) => write!(f, "f{}", number),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+/// Actions bound to the keys, in each context.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: HashMap<(KeyContext, KeyBinding), KeyAction>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        keymap.bind_all(DEFAULT_BINDINGS);
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Keymap of the named preset.
+    pub fn preset(name: &str) -> AppResult<Self> {
+        let (_, bindings) = PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .ok_or_else(|| Error::Parse(format!("Unknown keymap preset: {}", name)))?;
+
+        let mut keymap = Self::default();
+        keymap.bind_all(bindings);
+        Ok(keymap)
+    }
+
+    /// Keymap of the preset of the configuration, with the remapped actions bound to their new
+    /// keys only.
+    pub fn from_config(config: &KeymapConfig) -> AppResult<Self> {
+        let mut keymap = Self::preset(config.preset.as_deref().unwrap_or("default"))?;
+
+        for (name, keys) in config.bindings.iter() {
+            let action = KeyAction::from_name(name)
+                .ok_or_else(|| Error::Parse(format!("Unknown key action: {}", name)))?;
+            keymap.bindings.retain(|_, bound| *bound != action);
+            for key in keys.iter() {
+                keymap
+                    .bindings
+                    .insert((action.context(), KeyBinding::parse(key)?), action);
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    fn bind_all(&mut self, bindings: &[(&str, KeyAction)]) {
+        for (key, action) in bindings {
+            let key = KeyBinding::parse(key).expect("Invalid key in a keymap preset");
+            self.bindings.insert((action.context(), key), *action);
+        }
+    }
+
+    /// Action bound to the key pressed in the context, falling back to the main bindings.
+    pub fn get(&self, event: &KeyEvent, context: KeyContext) -> Option<KeyAction> {
+        let key = KeyBinding::from_event(event);
+        self.bindings
+            .get(&(context, key))
+            .or_else(|| self.bindings.get(&(KeyContext::Main, key)))
+            .copied()
+    }
+
+    /// Keys bound to the action, in alphabetical order.
+    pub fn keys(&self, action: KeyAction) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|((_, key), _)| key.to_string())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Shortest key bound to each of the actions, e.g. `j/k` for the keys selecting rows. `None`
+    /// if none of them is bound.
+    pub fn hint(&self, actions: &[KeyAction]) -> Option<String> {
+        let keys: Vec<String> = actions
+            .iter()
+            .filter_map(|action| {
+                // Keys are sorted, the first of the shortest ones is kept
+                let keys = self.keys(*action);
+                keys.into_iter().min_by_key(|key| key.chars().count())
+            })
+            .collect();
+        (!keys.is_empty()).then(|| keys.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Keys;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyBinding {
+        KeyBinding { code, modifiers }
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            KeyBinding::parse("q").unwrap(),
+            key(KeyCode::Char('q'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("G").unwrap(),
+            key(KeyCode::Char('G'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("-").unwrap(),
+            key(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("ctrl-alt-d").unwrap(),
+            key(
+                KeyCode::Char('d'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        assert_eq!(
+            KeyBinding::parse("ctrl--").unwrap(),
+            key(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyBinding::parse("PageUp").unwrap(),
+            key(KeyCode::PageUp, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("space").unwrap(),
+            key(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyBinding::parse("f10").unwrap(),
+            key(KeyCode::F(10), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        for text in ["", "ctrl", "ctrl-", "ctrl-nope", "f0", "f13", "fx", "qq"] {
+            assert!(
+                KeyBinding::parse(text).is_err(),
+                "{} should not parse",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn displays_what_is_parsed() {
+        for text in [
+            "q", "ctrl-d", "alt-x", "space", "esc", "enter", "backtab", "pagedown", "f5",
+        ] {
+            assert_eq!(KeyBinding::parse(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn matches_events_regardless_of_shift_on_characters() {
+        let event = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(
+            KeyBinding::from_event(&event(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            KeyBinding::parse("G").unwrap()
+        );
+        assert_eq!(
+            KeyBinding::from_event(&event(KeyCode::BackTab, KeyModifiers::SHIFT)),
+            KeyBinding::parse("backtab").unwrap()
+        );
+        assert_eq!(
+            KeyBinding::from_event(&event(KeyCode::Up, KeyModifiers::SHIFT)),
+            key(KeyCode::Up, KeyModifiers::SHIFT)
+        );
+    }
+
+    #[test]
+    fn remaps_actions() {
+        let config = KeymapConfig {
+            preset: Some(String::from("vim")),
+            bindings: [(
+                String::from("quit"),
+                Keys::Many(vec![String::from("ctrl-q"), String::from("Q")]),
+            )]
+            .into(),
+        };
+        let keymap = Keymap::from_config(&config).unwrap();
+        assert_eq!(keymap.keys(KeyAction::Quit), ["Q", "ctrl-q"]);
+
+        let unknown = KeymapConfig {
+            bindings: [(String::from("fly"), Keys::One(String::from("f")))].into(),
+            ..KeymapConfig::default()
+        };
+        assert!(Keymap::from_config(&unknown).is_err());
+        assert!(Keymap::preset("emacs").is_err());
+    }
+
+    #[test]
+    fn looks_up_the_context_before_the_main_bindings() {
+        let keymap = Keymap::preset("vim").unwrap();
+        let event = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        assert_eq!(
+            keymap.get(&event('g'), KeyContext::Main),
+            Some(KeyAction::SelectFirst)
+        );
+        assert_eq!(
+            keymap.get(&event('g'), KeyContext::Results),
+            Some(KeyAction::ToggleGrouping)
+        );
+        assert_eq!(
+            keymap.get(&event('j'), KeyContext::Results),
+            Some(KeyAction::SelectNext)
+        );
+        assert_eq!(
+            keymap.hint(&[KeyAction::SelectNext, KeyAction::SelectPrevious]),
+            Some(String::from("j/k"))
+        );
+    }
+}
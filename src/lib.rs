@@ -1,6 +1,10 @@
 /// Application.
 pub mod app;
 
+/// Error types
+pub mod error;
+pub use error::Error;
+
 /// Terminal events handler.
 pub mod event;
 
@@ -15,3 +19,42 @@ pub mod handler;
 
 /// Secure shell interface
 pub mod ssh;
+
+/// Command line interface
+pub mod cli;
+
+/// Session recording and replay
+pub mod record;
+
+/// Prometheus metrics exporter
+pub mod exporter;
+
+/// Ad-hoc commands run across hosts
+pub mod command;
+
+/// Nodeset expansion and folding
+pub mod nodes;
+
+/// Long-lived remote sampler
+pub mod sampler;
+
+/// Event log and capture of the log records
+pub mod history;
+
+/// Configuration file
+pub mod config;
+
+/// Alert rules evaluated on every host
+pub mod alert;
+
+/// Static facts collected from the hosts
+pub mod inventory;
+
+/// Configuration drift detection across hosts
+pub mod drift;
+
+/// Configurable key bindings
+pub mod keymap;
+
+/// Colour themes of the interface
+pub mod theme;
@@ -1,78 +1,455 @@
-use jbtop::app::{App, AppResult};
-use jbtop::event::{Event, EventHandler};
-use jbtop::handler::{handle_host_events, handle_key_events, handle_load_events};
+use clap::Parser;
+use futures::future;
+use jbtop::alert::{self, Alerts};
+use jbtop::app::{Action, App, AppResult};
+use jbtop::cli::{Args, SamplerMode};
+use jbtop::config::Config;
+use jbtop::drift::Check;
+use jbtop::event::{self, Event, EventHandler, EventSender, EVENT_CAPACITY};
+use jbtop::exporter;
+use jbtop::handler::{
+    handle_command_events, handle_drift_events, handle_host_events, handle_key_events,
+    handle_load_events, handle_mouse_events, handle_replay_events,
+};
+use jbtop::history::Logger;
+use jbtop::keymap::Keymap;
+use jbtop::nodes;
+use jbtop::record::{self, Recorder};
 use jbtop::ssh;
+use jbtop::theme::Theme;
 use jbtop::tui::Tui;
+use jbtop::Error;
 use log::LevelFilter;
 use ratatui::{backend::CrosstermBackend, Terminal};
 use simple_logger::SimpleLogger;
-use std::{collections::HashMap, error::Error, io, sync::Arc};
-use tokio::sync::Mutex;
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::time::Instant;
+
+/// Time given to the sessions to disconnect on exit.
+const DISCONNECT_DEADLINE: Duration = Duration::from_secs(3);
+
+/// Time between two samples of the Lustre mounts and health, which seldom change.
+const LUSTRE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Shortest time between two renders of the interface.
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How the hosts are reached and sampled, shared by the hosts added at runtime.
+struct Sampling {
+    sender: EventSender,
+    sampler: SamplerMode,
+    period: Duration,
+    timeout: Duration,
+    /// Limits the number of connections established at the same time.
+    fanout: Arc<Semaphore>,
+}
+
+/// Session of a host, along with the tasks connecting to and sampling it.
+struct HostTasks {
+    connection: ssh::Connection,
+    handlers: Vec<EventHandler>,
+}
+
+impl HostTasks {
+    /// Spawns the connection and load tasks of the node, sharing one session.
+    fn spawn(node: &str, sampling: &Sampling) -> Self {
+        let connection = ssh::Connection::default();
+        let sender = &sampling.sender;
+        let handlers = vec![
+            EventHandler::connection(
+                sender.clone(),
+                node,
+                connection.clone(),
+                Arc::clone(&sampling.fanout),
+                sampling.timeout,
+            ),
+            match sampling.sampler {
+                SamplerMode::Exec => EventHandler::load(
+                    sender.clone(),
+                    node,
+                    connection.clone(),
+                    sampling.period,
+                    sampling.timeout,
+                ),
+                SamplerMode::Stream => EventHandler::sampler(
+                    sender.clone(),
+                    node,
+                    connection.clone(),
+                    sampling.period,
+                    sampling.timeout,
+                ),
+            },
+            EventHandler::lustre(
+                sender.clone(),
+                node,
+                connection.clone(),
+                LUSTRE_PERIOD.max(sampling.period),
+                sampling.timeout,
+            ),
+        ];
+
+        Self {
+            connection,
+            handlers,
+        }
+    }
+
+    /// Stops the tasks of the host, then disconnects its session.
+    async fn close(self, node: String) {
+        let connection = self.stop().await;
+        disconnect(connection, node).await;
+    }
+
+    /// Stops the tasks of the host, returning its connection still open.
+    async fn stop(self) -> ssh::Connection {
+        future::join_all(self.handlers.into_iter().map(EventHandler::stop)).await;
+        self.connection
+    }
+}
+
+/// Disconnects the session of the host, if connected.
+async fn disconnect(connection: ssh::Connection, node: String) {
+    if let Some(session) = connection.take() {
+        if let Err(e) = session.close().await {
+            log::debug!("Failed to close the session of {}: {}", node, e);
+        }
+    }
+}
+
+/// Stops the tasks of every host and disconnects their sessions concurrently, dropping the
+/// sessions still open after [`DISCONNECT_DEADLINE`].
+async fn close_hosts(session_pool: HashMap<String, HostTasks>) {
+    let closing = future::join_all(
+        session_pool
+            .into_iter()
+            .map(|(node, tasks)| tasks.close(node)),
+    );
+    if tokio::time::timeout(DISCONNECT_DEADLINE, closing)
+        .await
+        .is_err()
+    {
+        log::warn!(
+            "Sessions still open after {:?}, dropping them",
+            DISCONNECT_DEADLINE
+        );
+    }
+}
+
+/// Signals asking the application to exit.
+struct Signals {
+    terminate: Signal,
+    hangup: Signal,
+    interrupt: Signal,
+}
+
+impl Signals {
+    fn new() -> AppResult<Self> {
+        Ok(Self {
+            terminate: signal(SignalKind::terminate())?,
+            hangup: signal(SignalKind::hangup())?,
+            interrupt: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    /// Waits for one of the signals, returning its name.
+    async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.hangup.recv() => "SIGHUP",
+            _ = self.interrupt.recv() => "SIGINT",
+        }
+    }
+}
+
+/// Spawns the tasks of every node.
+fn spawn_hosts(nodes: &[(String, String)], sampling: &Sampling) -> HashMap<String, HostTasks> {
+    nodes
+        .iter()
+        .map(|(node, _)| (node.clone(), HostTasks::spawn(node, sampling)))
+        .collect()
+}
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
-    SimpleLogger::new()
-        .with_level(LevelFilter::Debug)
-        .with_module_level("russh", LevelFilter::Info)
-        .init()
-        .unwrap();
+    let args = Args::parse();
 
-    let noderange = std::env::args()
-        .nth(1)
-        .ok_or("Missing the nodeset argument !")?;
-    let nodes: Vec<String> = noderange
-        .split(',')
-        .map(|set| nodeset::node::node_to_vec_string(set))
-        .collect::<Result<Vec<Vec<String>>, Box<dyn Error>>>()?
-        .iter_mut()
-        .flat_map(|set| set.drain(..))
-        .collect();
+    match args.serve {
+        Some(addr) => {
+            SimpleLogger::new()
+                .with_level(LevelFilter::Debug)
+                .with_module_level("russh", LevelFilter::Info)
+                .init()
+                .unwrap();
+            serve(args, addr).await
+        }
+        None => interactive(args).await,
+    }
+}
+
+/// Runs the polling machinery without a terminal, exposing the results to Prometheus.
+async fn serve(args: Args, addr: SocketAddr) -> AppResult<()> {
+    let noderange = args
+        .nodeset
+        .ok_or_else(|| Error::Parse(String::from("Missing the nodeset argument !")))?;
+    let nodes = nodes::expand(&noderange)?;
 
+    let config = Config::load(args.config.as_deref())?;
+
+    let period = Duration::from_millis(args.interval);
+    let mut app = App::new();
+    app.stale_after = period * args.stale_after;
+    app.alerts = Alerts::from_config(&config.alerts)?;
+    for (node, group) in nodes.iter() {
+        app.add_host(node, group);
+    }
+    let app = Arc::new(Mutex::new(app));
+
+    let (sender, mut receiver) = event::channel(EVENT_CAPACITY);
+    let sampling = Sampling {
+        sender,
+        sampler: args.sampler,
+        period,
+        timeout: Duration::from_millis(args.timeout),
+        fanout: Arc::new(Semaphore::new(args.fanout as usize)),
+    };
+    let session_pool = spawn_hosts(&nodes, &sampling);
+    let server = exporter::serve(addr, Arc::clone(&app));
+    tokio::pin!(server);
+
+    // Errors end the main loop but the sessions must still be closed
+    let result: AppResult<()> = async {
+        let mut recorder = args.record.map(Recorder::create).transpose()?;
+        let mut tick = tokio::time::interval(period);
+        let mut signals = Signals::new()?;
+
+        loop {
+            let event = tokio::select! {
+                result = &mut server => break result,
+                signal = signals.recv() => {
+                    log::info!("Received {}, exiting", signal);
+                    break Ok(());
+                }
+                _ = tick.tick() => {
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.flush_if_due()?;
+                    }
+                    let mut app = app.lock().await;
+                    app.check_alerts();
+                    for action in app.take_actions() {
+                        if let Action::Hook { command, env } = action {
+                            alert::run_hook(&command, env);
+                        }
+                    }
+                    continue;
+                }
+                event = receiver.recv() => {
+                    event.ok_or_else(|| Error::Io(String::from("Event channel closed")))?
+                }
+            };
+
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&event)?;
+            }
+
+            let mut app = app.lock().await;
+            match event {
+                Event::HostStatus(host, event) => handle_host_events(&host, event, &mut app)?,
+                Event::LoadStatus(host, event) => handle_load_events(&host, event, &mut app)?,
+                _ => {}
+            }
+        }
+    }
+    .await;
+
+    close_hosts(session_pool).await;
+    result
+}
+
+/// Runs the terminal user interface.
+async fn interactive(args: Args) -> AppResult<()> {
     // Create an application.
+    let config = Config::load(args.config.as_deref())?;
+    let period = Duration::from_millis(args.interval);
     let mut app = App::new();
+    app.stale_after = period * args.stale_after;
+    app.alerts = Alerts::from_config(&config.alerts)?;
+    app.checks = Check::from_config(&config.drift);
+    app.keymap = Keymap::from_config(&config.keymap)?;
+    app.theme = Theme::from_config(
+        args.theme.as_deref().or(config.theme.as_deref()),
+        &config.themes,
+    )?;
+    let timeout = Duration::from_millis(args.timeout);
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stdout());
     let terminal = Terminal::new(backend)?;
     let mut tui = Tui::new(terminal);
 
+    // Log to the log pane, as anything written to the terminal would garble the interface
+    Logger::new(tui.channel())
+        .with_level(LevelFilter::Debug)
+        .with_module_level("russh", LevelFilter::Info)
+        .init()
+        .unwrap();
+
     let mut events: Vec<EventHandler> = vec![EventHandler::terminal(tui.channel(), 250)];
 
-    let mut session_pool = HashMap::<String, Arc<Mutex<Option<ssh::Session>>>>::new();
-    for node in nodes.iter() {
-        let connection = Arc::new(Mutex::new(None));
-        events.push(EventHandler::connection(
+    let sampling = Sampling {
+        sender: tui.channel(),
+        sampler: args.sampler,
+        period,
+        timeout,
+        fanout: Arc::new(Semaphore::new(args.fanout as usize)),
+    };
+    let mut session_pool = HashMap::new();
+    let mut commands: Vec<EventHandler> = vec![];
+    if let Some(path) = args.replay.as_ref() {
+        let records = record::load(path)?;
+        let (control, receiver) = mpsc::unbounded_channel();
+        events.push(EventHandler::replay(
             tui.channel(),
-            node,
-            Arc::clone(&connection),
+            records,
+            args.speed,
+            receiver,
         ));
-        events.push(EventHandler::load(
-            tui.channel(),
-            node,
-            Arc::clone(&connection),
-        ));
-        session_pool.insert(node.clone(), connection);
+        app.start_replay(control);
+    } else {
+        let noderange = args
+            .nodeset
+            .ok_or_else(|| Error::Parse(String::from("Missing the nodeset argument !")))?;
+        let nodes = nodes::expand(&noderange)?;
+        for (node, group) in nodes.iter() {
+            app.add_host(node, group);
+        }
+        session_pool = spawn_hosts(&nodes, &sampling);
     }
 
-    tui.init()?;
-
-    // Start the main loop.
-    while app.running {
-        // Render the user interface.
-        tui.draw(&mut app)?;
-        // Handle events.
-        match tui.next().await? {
-            Event::Tick => app.tick(),
-            Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
-            Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
-            Event::HostStatus(host, event) => handle_host_events(&host, event, &mut app)?,
-            Event::LoadStatus(host, event) => handle_load_events(&host, event, &mut app)?,
+    // Errors end the main loop but the tasks and the terminal must still be cleaned up
+    let result: AppResult<()> = async {
+        let mut recorder = args.record.map(Recorder::create).transpose()?;
+
+        tui.init()?;
+        let mut signals = Signals::new()?;
+
+        // Start the main loop.
+        let mut last_draw = Instant::now();
+        let mut dirty = true;
+        while app.running {
+            // Render the user interface, at most once per frame however many events come in.
+            if dirty && last_draw.elapsed() >= FRAME_INTERVAL {
+                tui.draw(&mut app)?;
+                last_draw = Instant::now();
+                dirty = false;
+            }
+            // Handle events.
+            let event = tokio::select! {
+                event = tui.next() => event?,
+                _ = tokio::time::sleep_until(last_draw + FRAME_INTERVAL), if dirty => continue,
+                signal = signals.recv() => {
+                    log::info!("Received {}, exiting", signal);
+                    app.quit();
+                    continue;
+                }
+            };
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&event)?;
+            }
+
+            dirty = true;
+            match event {
+                Event::Tick => app.tick(),
+                Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
+                Event::Mouse(mouse_event) => handle_mouse_events(mouse_event, &mut app)?,
+                Event::Resize(_, _) => {}
+                Event::HostStatus(host, event) => handle_host_events(&host, event, &mut app)?,
+                Event::LoadStatus(host, event) => handle_load_events(&host, event, &mut app)?,
+                Event::CommandStatus(host, id, event) => {
+                    handle_command_events(&host, id, event, &mut app)?
+                }
+                Event::DriftStatus(host, id, event) => {
+                    handle_drift_events(&host, id, event, &mut app)?
+                }
+                Event::Replay(event) => handle_replay_events(event, &mut app)?,
+                Event::Removed(host) => app.finish_removal(&host),
+                Event::Log(entry) => app.log(entry),
+            }
+
+            for action in app.take_actions() {
+                match action {
+                    Action::Exec { id, command, hosts } => {
+                        for host in hosts.iter() {
+                            let connection = session_pool
+                                .get(host)
+                                .map(|tasks| tasks.connection.clone())
+                                .unwrap_or_default();
+                            commands.push(EventHandler::command(
+                                tui.channel(),
+                                host,
+                                connection,
+                                id,
+                                &command,
+                                timeout,
+                            ));
+                        }
+                    }
+                    Action::Drift {
+                        id,
+                        commands: checks,
+                        hosts,
+                    } => {
+                        for host in hosts.iter() {
+                            let connection = session_pool
+                                .get(host)
+                                .map(|tasks| tasks.connection.clone())
+                                .unwrap_or_default();
+                            commands.push(EventHandler::drift(
+                                tui.channel(),
+                                host,
+                                connection,
+                                id,
+                                checks.clone(),
+                                timeout,
+                            ));
+                        }
+                    }
+                    Action::CancelExec => commands.drain(..).for_each(|handler| handler.abort()),
+                    Action::Hook { command, env } => alert::run_hook(&command, env),
+                    Action::AddHosts(nodes) => {
+                        for (node, _) in nodes.iter() {
+                            session_pool.insert(node.clone(), HostTasks::spawn(node, &sampling));
+                        }
+                    }
+                    Action::RemoveHosts(nodes) => {
+                        for node in nodes {
+                            let Some(tasks) = session_pool.remove(&node) else {
+                                continue;
+                            };
+                            // Tell the app once the events of the host stopped, in case it is added
+                            // back while they are still queued
+                            let sender = tui.channel();
+                            tokio::spawn(async move {
+                                let connection = tasks.stop().await;
+                                let _ = sender.send(Event::Removed(node.clone())).await;
+                                disconnect(connection, node).await;
+                            });
+                        }
+                    }
+                }
+            }
         }
+
+        Ok(())
     }
+    .await;
 
-    // Exit the user interface.
-    tui.exit()?;
+    // Stop reading the terminal and running commands, then give the terminal back before
+    // waiting for the sessions to disconnect.
+    future::join_all(events.into_iter().chain(commands).map(EventHandler::stop)).await;
+    let exited = tui.exit();
+    close_hosts(session_pool).await;
 
-    Ok(())
+    result.and(exited)
 }
@@ -0,0 +1,172 @@
+use crate::app::AppResult;
+use crate::error::Error;
+use std::collections::{BTreeMap, HashSet};
+
+/// Expands a nodeset into a list of hosts, along with the element of the nodeset they come from.
+pub fn expand(noderange: &str) -> AppResult<Vec<(String, String)>> {
+    let mut nodes = vec![];
+    for group in split_groups(noderange) {
+        let expanded = nodeset::node::node_to_vec_string(group)
+            .map_err(|e| Error::Parse(format!("Invalid nodeset {}: {}", group, e)))?;
+        for node in expanded {
+            nodes.push((node, group.to_string()));
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Splits a nodeset on the commas that are not inside brackets, so that `node[1-3,5],login1`
+/// yields `node[1-3,5]` and `login1`.
+fn split_groups(noderange: &str) -> Vec<&str> {
+    let mut groups = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in noderange.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                groups.push(&noderange[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    groups.push(&noderange[start..]);
+    groups
+}
+
+/// Folds a list of hosts into a nodeset, e.g. `node[001-128,130],login1`.
+///
+/// Hosts are folded on the last number found in their name. Hosts whose numbers are padded
+/// differently are folded separately, so that expanding the result yields the original list.
+pub fn fold<S: AsRef<str>>(hosts: &[S]) -> String {
+    // (prefix, digits, suffix) of every host with a number in its name
+    let mut numbered = vec![];
+    let mut others = vec![];
+    for host in hosts.iter().map(AsRef::as_ref) {
+        let Some(end) = host.rfind(|c: char| c.is_ascii_digit()).map(|i| i + 1) else {
+            others.push(host.to_string());
+            continue;
+        };
+        let start = host[..end]
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |i| i + 1);
+        numbered.push((&host[..start], &host[start..end], &host[end..]));
+    }
+
+    let padded = |digits: &str| digits.len() > 1 && digits.starts_with('0');
+    let widths: HashSet<(&str, &str, usize)> = numbered
+        .iter()
+        .filter(|(_, digits, _)| padded(digits))
+        .map(|(prefix, digits, suffix)| (*prefix, *suffix, digits.len()))
+        .collect();
+
+    // (prefix, suffix, padding) -> numbers
+    let mut patterns = BTreeMap::<(&str, &str, usize), Vec<u64>>::new();
+    for (prefix, digits, suffix) in numbered {
+        let Ok(number) = digits.parse::<u64>() else {
+            others.push(format!("{}{}{}", prefix, digits, suffix));
+            continue;
+        };
+        // Numbers as wide as the padded ones of the same pattern are folded along with them
+        let padding = match padded(digits) || widths.contains(&(prefix, suffix, digits.len())) {
+            true => digits.len(),
+            false => 0,
+        };
+
+        patterns
+            .entry((prefix, suffix, padding))
+            .or_default()
+            .push(number);
+    }
+
+    let mut folded: Vec<String> = patterns
+        .into_iter()
+        .map(|((prefix, suffix, padding), mut numbers)| {
+            numbers.sort_unstable();
+            numbers.dedup();
+            match numbers[..] {
+                [single] => format!("{}{:0padding$}{}", prefix, single, suffix),
+                _ => format!("{}[{}]{}", prefix, fold_numbers(&numbers, padding), suffix),
+            }
+        })
+        .collect();
+
+    others.sort();
+    others.dedup();
+    folded.extend(others);
+    folded.join(",")
+}
+
+/// Folds a sorted list of numbers into ranges, e.g. `1-3,5`.
+fn fold_numbers(numbers: &[u64], padding: usize) -> String {
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    for &number in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == number => *end = number,
+            _ => ranges.push((number, number)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| match start == end {
+            true => format!("{:0padding$}", start),
+            false => format!("{:0padding$}-{:0padding$}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(noderange: &str) -> Vec<String> {
+        expand(noderange)
+            .unwrap()
+            .into_iter()
+            .map(|(host, _)| host)
+            .collect()
+    }
+
+    #[test]
+    fn expand_splits_outside_brackets() {
+        let nodes = expand("node[1-2,4],login1").unwrap();
+        assert_eq!(
+            nodes,
+            [
+                ("node1".to_string(), "node[1-2,4]".to_string()),
+                ("node2".to_string(), "node[1-2,4]".to_string()),
+                ("node4".to_string(), "node[1-2,4]".to_string()),
+                ("login1".to_string(), "login1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_numbers_into_ranges() {
+        assert_eq!(fold(&["n3", "n1", "n2", "n5", "login"]), "n[1-3,5],login");
+        assert_eq!(fold(&["n01", "n02", "n10"]), "n[01-02,10]");
+        assert_eq!(fold(&["n1", "n01"]), "n1,n01");
+    }
+
+    #[test]
+    fn fold_then_expand_round_trips() {
+        for original in [
+            vec!["node001", "node002", "node003", "node005"],
+            vec!["n1", "n2", "n10", "login1", "login2"],
+            vec!["n1", "n01", "n002"],
+            vec!["rack1-n1", "rack1-n2", "rack2-n1"],
+            vec!["gpu1a", "gpu2a", "gpu3b", "frontend"],
+        ] {
+            let mut expanded = hosts(&fold(&original));
+            let mut original: Vec<String> = original.into_iter().map(String::from).collect();
+            expanded.sort();
+            original.sort();
+            assert_eq!(expanded, original, "{}", fold(&original));
+        }
+    }
+}
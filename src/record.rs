@@ -0,0 +1,430 @@
+use crate::app::AppResult;
+use crate::error::{Error, ErrorClass};
+use crate::event::{ConnectionEvent, Event, LoadEvent};
+use crate::inventory::Inventory;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// First line of every recording, used to reject foreign files.
+static HEADER: &str = "jbtop-record 4";
+
+/// Longest time events stay buffered before being written to the file.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Commands sent from the interface to the replay task.
+#[derive(Clone, Copy, Debug)]
+pub enum ReplayCommand {
+    TogglePause,
+    SeekForward(Duration),
+    SeekBackward(Duration),
+    /// Multiply the current speed by the given factor.
+    Speed(f64),
+}
+
+/// Writes monitoring events to a file as they are received.
+///
+/// Each event is stored on its own line as tab-separated fields: milliseconds since the start of
+/// the recording, hostname, event kind and payload. Only host events are recorded, terminal events
+/// are meaningless once replayed.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+    last_flush: Instant,
+}
+
+impl Recorder {
+    /// Creates the recording file, truncating it if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> AppResult<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Appends the event to the recording if it is a host event.
+    ///
+    /// The buffer is flushed regularly so that a crash loses at most [`FLUSH_INTERVAL`] worth of
+    /// events.
+    pub fn record(&mut self, event: &Event) -> AppResult<()> {
+        self.flush_if_due()?;
+
+        if let Some((host, kind, payload)) = encode(event) {
+            writeln!(
+                self.writer,
+                "{}\t{}\t{}\t{}",
+                self.start.elapsed().as_millis(),
+                escape(host),
+                kind,
+                escape(&payload)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the buffered events once they waited for [`FLUSH_INTERVAL`]. Called on a timer as
+    /// well as on every event, so that events do not stay buffered while no other comes in.
+    pub fn flush_if_due(&mut self) -> AppResult<()> {
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// Reads a recording, returning its events along with their offset from the start.
+///
+/// Records are written a line at a time, so a last line missing its newline was cut short by a
+/// crash and is dropped. Any other invalid line fails the whole recording.
+pub fn load<P: AsRef<Path>>(path: P) -> AppResult<Vec<(Duration, Event)>> {
+    let mut contents = std::fs::read(path)?;
+    let complete = contents
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |end| end + 1);
+    if complete < contents.len() {
+        log::warn!("Dropping the truncated last line of the recording");
+        contents.truncate(complete);
+    }
+    let contents = String::from_utf8(contents)
+        .map_err(|_| Error::Parse(String::from("Invalid UTF-8 in the recording")))?;
+    let mut lines = contents.lines();
+
+    match lines.next() {
+        Some(header) if header == HEADER => (),
+        _ => return Err(Error::Parse(String::from("Not a jbtop recording !"))),
+    }
+
+    let mut events = vec![];
+    for (index, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.splitn(4, '\t').collect();
+        let event = match fields[..] {
+            [offset, host, kind, payload] => {
+                offset
+                    .parse::<u64>()
+                    .ok()
+                    .zip(decode(&unescape(host), kind, unescape(payload)))
+            }
+            _ => None,
+        };
+
+        match event {
+            Some((offset, event)) => events.push((Duration::from_millis(offset), event)),
+            None => {
+                return Err(Error::Parse(format!(
+                    "Invalid record on line {}",
+                    index + 2
+                )))
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+fn encode(event: &Event) -> Option<(&str, &'static str, String)> {
+    match event {
+        Event::HostStatus(host, event) => Some(match event {
+            ConnectionEvent::Queued => (host, "queued", String::new()),
+            ConnectionEvent::Connecting => (host, "connecting", String::new()),
+            ConnectionEvent::Authenticating => (host, "authenticating", String::new()),
+            ConnectionEvent::Connected(handshake) => {
+                (host, "connected", handshake.as_micros().to_string())
+            }
+            ConnectionEvent::Backoff(delay, e) => (
+                host,
+                "backoff",
+                format!("{} {}", delay.as_millis(), encode_error(e)),
+            ),
+            ConnectionEvent::Failed(e) => (host, "failed", encode_error(e)),
+        }),
+        Event::LoadStatus(host, event) => Some(match event {
+            LoadEvent::Load(load) => (host, "load", load.clone()),
+            LoadEvent::Latency(latency) => (host, "latency", latency.as_micros().to_string()),
+            LoadEvent::Memory(total, available) => {
+                (host, "memory", format!("{} {}", total, available))
+            }
+            LoadEvent::Inventory(inventory) => (host, "inventory", inventory.encode()),
+            LoadEvent::Lustre(mounts, health) => {
+                let inventory = Inventory {
+                    lustre: mounts.clone(),
+                    lustre_health: health.clone(),
+                    ..Inventory::default()
+                };
+                (host, "lustre", inventory.encode())
+            }
+            LoadEvent::LoadError(e) => (host, "load-error", encode_error(e)),
+        }),
+        _ => None,
+    }
+}
+
+fn decode(host: &str, kind: &str, payload: String) -> Option<Event> {
+    let host = host.to_string();
+    let event = match kind {
+        "queued" => Event::HostStatus(host, ConnectionEvent::Queued),
+        "connecting" => Event::HostStatus(host, ConnectionEvent::Connecting),
+        "authenticating" => Event::HostStatus(host, ConnectionEvent::Authenticating),
+        "connected" => {
+            let handshake = Duration::from_micros(payload.parse().ok()?);
+            Event::HostStatus(host, ConnectionEvent::Connected(handshake))
+        }
+        "backoff" => {
+            let (delay, error) = payload.split_once(' ')?;
+            let delay = Duration::from_millis(delay.parse().ok()?);
+            Event::HostStatus(host, ConnectionEvent::Backoff(delay, decode_error(error)?))
+        }
+        "failed" => Event::HostStatus(host, ConnectionEvent::Failed(decode_error(&payload)?)),
+        "load" => Event::LoadStatus(host, LoadEvent::Load(payload)),
+        "load-error" => Event::LoadStatus(host, LoadEvent::LoadError(decode_error(&payload)?)),
+        "latency" => {
+            let latency = Duration::from_micros(payload.parse().ok()?);
+            Event::LoadStatus(host, LoadEvent::Latency(latency))
+        }
+        "memory" => {
+            let (total, available) = payload.split_once(' ')?;
+            Event::LoadStatus(
+                host,
+                LoadEvent::Memory(total.parse().ok()?, available.parse().ok()?),
+            )
+        }
+        "inventory" => Event::LoadStatus(
+            host,
+            LoadEvent::Inventory(Box::new(Inventory::parse(&payload))),
+        ),
+        "lustre" => {
+            let inventory = Inventory::parse(&payload);
+            Event::LoadStatus(
+                host,
+                LoadEvent::Lustre(inventory.lustre, inventory.lustre_health),
+            )
+        }
+        _ => return None,
+    };
+
+    Some(event)
+}
+
+/// Stores an error as its class followed by its details: the timeout in microseconds, the exit
+/// code and stderr, or the message.
+fn encode_error(error: &Error) -> String {
+    let details = match error {
+        Error::Dns(e)
+        | Error::Tcp(e)
+        | Error::Auth(e)
+        | Error::HostKey(e)
+        | Error::Channel(e)
+        | Error::Exec(e)
+        | Error::Parse(e)
+        | Error::Io(e) => e.clone(),
+        Error::Timeout(timeout) => timeout.as_micros().to_string(),
+        Error::Exit(code, stderr) => format!("{} {}", code, stderr),
+    };
+    format!("{} {}", error.class().name(), details)
+}
+
+fn decode_error(payload: &str) -> Option<Error> {
+    let (class, details) = payload.split_once(' ')?;
+    let details = details.to_string();
+    let error = match ErrorClass::from_name(class)? {
+        ErrorClass::Dns => Error::Dns(details),
+        ErrorClass::Tcp => Error::Tcp(details),
+        ErrorClass::Auth => Error::Auth(details),
+        ErrorClass::HostKey => Error::HostKey(details),
+        ErrorClass::Channel => Error::Channel(details),
+        ErrorClass::Exec => Error::Exec(details),
+        ErrorClass::Timeout => Error::Timeout(Duration::from_micros(details.parse().ok()?)),
+        ErrorClass::Parse => Error::Parse(details),
+        ErrorClass::Exit => {
+            let (code, stderr) = details.split_once(' ')?;
+            Error::Exit(code.parse().ok()?, stderr.to_string())
+        }
+        ErrorClass::Io => Error::Io(details),
+    };
+
+    Some(error)
+}
+
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Events are compared through their debug output, they cannot be compared otherwise.
+    fn round_trip(event: Event) {
+        let (host, kind, payload) = encode(&event).unwrap();
+        let decoded = decode(&unescape(&escape(host)), kind, unescape(&escape(&payload)));
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", Some(event)));
+    }
+
+    #[test]
+    fn escapes_fields() {
+        for field in [
+            "",
+            "plain",
+            "a\tb\nc",
+            "back\\slash",
+            "\\n",
+            "trailing\\",
+            "é\t",
+        ] {
+            let escaped = escape(field);
+            assert!(!escaped.contains(['\t', '\n']), "{:?}", escaped);
+            assert_eq!(unescape(&escaped), field);
+        }
+    }
+
+    #[test]
+    fn decodes_what_is_encoded() {
+        let host = || String::from("node\t1");
+        round_trip(Event::HostStatus(host(), ConnectionEvent::Queued));
+        round_trip(Event::HostStatus(host(), ConnectionEvent::Connecting));
+        round_trip(Event::HostStatus(host(), ConnectionEvent::Authenticating));
+        round_trip(Event::HostStatus(
+            host(),
+            ConnectionEvent::Connected(Duration::from_micros(1234)),
+        ));
+        round_trip(Event::HostStatus(
+            host(),
+            ConnectionEvent::Backoff(Duration::from_millis(1500), Error::Tcp("refused".into())),
+        ));
+        round_trip(Event::HostStatus(
+            host(),
+            ConnectionEvent::Failed(Error::Auth("Key rejected".into())),
+        ));
+        round_trip(Event::LoadStatus(
+            host(),
+            LoadEvent::Load("0.1 0.2 0.3 1/2 3".into()),
+        ));
+        round_trip(Event::LoadStatus(
+            host(),
+            LoadEvent::Latency(Duration::from_micros(5678)),
+        ));
+        round_trip(Event::LoadStatus(host(), LoadEvent::Memory(1024, 512)));
+        round_trip(Event::LoadStatus(
+            host(),
+            LoadEvent::Inventory(Box::new(Inventory::parse("kernel=6.1\ncpus=8"))),
+        ));
+        round_trip(Event::LoadStatus(
+            host(),
+            LoadEvent::Lustre(Some("10.0.0.1@tcp:/fs".into()), None),
+        ));
+        for error in [
+            Error::Dns("unknown".into()),
+            Error::HostKey("changed".into()),
+            Error::Channel("closed".into()),
+            Error::Exec("killed".into()),
+            Error::Timeout(Duration::from_millis(2500)),
+            Error::Parse("garbage".into()),
+            Error::Exit(127, "not found\nat all".into()),
+            Error::Exit(1, String::new()),
+            Error::Io("broken pipe".into()),
+        ] {
+            round_trip(Event::LoadStatus(host(), LoadEvent::LoadError(error)));
+        }
+    }
+
+    #[test]
+    fn skips_terminal_events() {
+        assert!(encode(&Event::Tick).is_none());
+        assert!(encode(&Event::Resize(80, 24)).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_payloads() {
+        assert!(decode("n1", "unknown", String::new()).is_none());
+        assert!(decode("n1", "latency", "soon".into()).is_none());
+        assert!(decode("n1", "memory", "1024".into()).is_none());
+        assert!(decode("n1", "failed", "nonsense details".into()).is_none());
+        assert!(decode("n1", "load-error", "exit abc".into()).is_none());
+    }
+
+    #[test]
+    fn loads_recordings() {
+        let path = std::env::temp_dir().join(format!("jbtop-record-{}", std::process::id()));
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder
+            .record(&Event::LoadStatus("n1".into(), LoadEvent::Memory(2, 1)))
+            .unwrap();
+        recorder.record(&Event::Tick).unwrap();
+        drop(recorder);
+
+        let events = load(&path).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0].1,
+            Event::LoadStatus(ref host, LoadEvent::Memory(2, 1)) if host == "n1"
+        ));
+
+        std::fs::write(&path, format!("{}\n0\tn1\tlatency\tsoon\n", HEADER)).unwrap();
+        assert!(matches!(load(&path), Err(Error::Parse(_))));
+        std::fs::write(&path, "jbtop-record 0\n").unwrap();
+        assert!(matches!(load(&path), Err(Error::Parse(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn drops_truncated_last_lines() {
+        let path = std::env::temp_dir().join(format!("jbtop-truncated-{}", std::process::id()));
+        let complete = format!("{}\n10\tn1\tmemory\t2 1\n", HEADER);
+
+        // Cut in the middle of a record, and of a multibyte character
+        let mut contents = format!("{}20\tn1\tload\t0.1 0.2", complete).into_bytes();
+        std::fs::write(&path, &contents).unwrap();
+        assert_eq!(load(&path).unwrap().len(), 1);
+        contents.truncate(complete.len());
+        contents.extend_from_slice(&"20\tnœud".as_bytes()[..5]);
+        std::fs::write(&path, &contents).unwrap();
+        assert_eq!(load(&path).unwrap().len(), 1);
+
+        // Only the last line may be cut short
+        let invalid = format!("{}20\tn1\tmemory\t2\n30\tn1\tmemory\t2 1", complete);
+        std::fs::write(&path, invalid).unwrap();
+        assert!(matches!(load(&path), Err(Error::Parse(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use crate::app::AppResult;
+use crate::error::Error;
+use std::time::Duration;
+
+/// Line closing every sample printed by [`script`].
+pub static DELIMITER: &str = "--- jbtop sample";
+
+/// Lines buffered before giving up on finding a delimiter, samples are only a few lines long.
+const MAX_LINES: usize = 64;
+
+/// Shell loop run on the hosts by the long-lived sampler, printing a sample every `interval`.
+pub fn script(interval: Duration) -> String {
+    format!(
+        "while :; do \
+        cat /proc/loadavg; \
+        grep -E '^(MemTotal|MemAvailable):' /proc/meminfo; \
+        echo '{}'; \
+        sleep {}; \
+        done",
+        DELIMITER,
+        interval.as_secs_f64()
+    )
+}
+
+/// Metrics read from a single sample.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sample {
+    /// Contents of `/proc/loadavg`.
+    pub load: Option<String>,
+    /// Total and available memory, in kB.
+    pub memory: Option<(u64, u64)>,
+}
+
+/// Incremental parser for the output of [`script`].
+#[derive(Debug, Default)]
+pub struct SampleParser {
+    lines: Vec<String>,
+}
+
+impl SampleParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a line of output, returning the sample it completes if any.
+    ///
+    /// Fails if too many lines are printed without a delimiter, the buffered lines are then
+    /// discarded.
+    pub fn push(&mut self, line: String) -> AppResult<Option<Sample>> {
+        if line != DELIMITER {
+            if self.lines.len() >= MAX_LINES {
+                self.lines.clear();
+                return Err(Error::Parse(format!(
+                    "No sample delimiter in {} lines of output",
+                    MAX_LINES
+                )));
+            }
+            self.lines.push(line);
+            return Ok(None);
+        }
+
+        let mut sample = Sample::default();
+        let (mut total, mut available) = (None, None);
+        for line in self.lines.drain(..) {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("MemTotal:") => total = fields.next().and_then(|v| v.parse().ok()),
+                Some("MemAvailable:") => available = fields.next().and_then(|v| v.parse().ok()),
+                Some(_) if sample.load.is_none() => sample.load = Some(line),
+                _ => (),
+            }
+        }
+        sample.memory = total.zip(available);
+
+        Ok(Some(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(parser: &mut SampleParser, lines: &[&str]) -> Vec<Sample> {
+        lines
+            .iter()
+            .filter_map(|line| parser.push(line.to_string()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parses_samples() {
+        let mut parser = SampleParser::new();
+        let samples = push_all(
+            &mut parser,
+            &[
+                "0.50 0.40 0.30 1/200 1234",
+                "MemTotal:       16000000 kB",
+                "MemAvailable:    8000000 kB",
+                DELIMITER,
+                "1.00 0.80 0.60 2/210 1240",
+            ],
+        );
+        assert_eq!(
+            samples,
+            [Sample {
+                load: Some("0.50 0.40 0.30 1/200 1234".into()),
+                memory: Some((16000000, 8000000)),
+            }]
+        );
+
+        let samples = push_all(&mut parser, &["MemTotal: 16000000 kB", DELIMITER]);
+        assert_eq!(
+            samples,
+            [Sample {
+                load: Some("1.00 0.80 0.60 2/210 1240".into()),
+                memory: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_empty_samples() {
+        let mut parser = SampleParser::new();
+        assert_eq!(push_all(&mut parser, &[DELIMITER]), [Sample::default()]);
+    }
+
+    #[test]
+    fn ignores_invalid_memory() {
+        let mut parser = SampleParser::new();
+        let samples = push_all(
+            &mut parser,
+            &["MemTotal: lots", "MemAvailable: 10 kB", DELIMITER],
+        );
+        assert_eq!(samples[0].memory, None);
+    }
+
+    #[test]
+    fn fails_without_delimiter() {
+        let mut parser = SampleParser::new();
+        for _ in 0..MAX_LINES {
+            assert!(parser.push("noise".into()).unwrap().is_none());
+        }
+        assert!(matches!(parser.push("noise".into()), Err(Error::Parse(_))));
+
+        // The parser starts over after the error
+        let samples = push_all(&mut parser, &["0.1 0.1 0.1 1/1 1", DELIMITER]);
+        assert_eq!(samples[0].load.as_deref(), Some("0.1 0.1 0.1 1/1 1"));
+    }
+}
@@ -1,15 +1,22 @@
+use crate::app::AppResult;
+use crate::error::Error;
 use async_trait::async_trait;
 use russh::*;
 use russh_keys::*;
-use std::error::Error;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::ToSocketAddrs;
+use tokio::net::{lookup_host, ToSocketAddrs};
+use tokio::sync::watch;
 
-static USER: &'static str = "jb";
-static PORT: &'static str = "2222";
-static SSH_KEY: &'static str = "/home/jb/.config/ssh/id_ed25519";
+static USER: &str = "jb";
+static PORT: &str = "2222";
+static SSH_KEY: &str = "/home/jb/.config/ssh/id_ed25519";
+
+/// Length past which an unterminated line is returned in pieces, so that a command printing
+/// without newlines does not grow its buffer without limit.
+const MAX_LINE: usize = 64 * 1024;
 
 pub struct Client {}
 
@@ -29,21 +36,95 @@ pub struct Session {
     pub handle: client::Handle<Client>,
 }
 
+/// Session of a host, shared by the tasks connecting to it and the collectors using it.
+///
+/// The session is published on a watch channel, so that collectors are told when the host
+/// connects or disconnects. Sessions are reference counted rather than locked, any number of
+/// collectors can open channels on them at the same time.
+#[derive(Clone)]
+pub struct Connection {
+    state: Arc<watch::Sender<Option<Arc<Session>>>>,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(watch::channel(None).0),
+        }
+    }
+}
+
+impl Connection {
+    /// Session of the host, if connected.
+    pub fn get(&self) -> Option<Arc<Session>> {
+        self.state.borrow().clone()
+    }
+
+    /// Waits for the host to be connected, returning its session.
+    pub async fn connected(&self) -> Arc<Session> {
+        let mut state = self.state.subscribe();
+        loop {
+            if let Some(session) = state.borrow_and_update().as_ref() {
+                return Arc::clone(session);
+            }
+            // The sender lives as long as self, the channel cannot be closed
+            let _ = state.changed().await;
+        }
+    }
+
+    /// Publishes the session of the newly connected host.
+    pub fn set(&self, session: Session) {
+        self.state.send_replace(Some(Arc::new(session)));
+    }
+
+    /// Discards the session to have the host reconnected, unless it was replaced already.
+    pub fn discard(&self, session: &Arc<Session>) {
+        self.state.send_if_modified(|state| match state {
+            Some(current) if Arc::ptr_eq(current, session) => {
+                *state = None;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Takes the session out, leaving the host disconnected.
+    pub fn take(&self) -> Option<Arc<Session>> {
+        self.state.send_replace(None)
+    }
+}
+
 pub struct Channel {
     pub channel: russh::Channel<client::Msg>,
+    /// Data received on stdout that does not make a full line yet.
+    stdout: Vec<u8>,
+    /// Data received on stderr that does not make a full line yet.
+    stderr: Vec<u8>,
+    timeout: Option<Duration>,
 }
 
-impl Session {
-    pub async fn new(hostname: &str) -> Result<Self, Box<dyn Error>> {
-        Session::connect(SSH_KEY, USER, format!("{}:{}", hostname, PORT)).await
-    }
+/// Output of a streamed command, as it is received.
+#[derive(Clone, Debug)]
+pub enum Output {
+    Stdout(String),
+    Stderr(String),
+    Exit(u32),
+}
+
+/// A connection to a host that completed the key exchange, but is not authenticated yet.
+pub struct Handshake {
+    handle: client::Handle<Client>,
+}
+
+impl Handshake {
+    /// Resolves the address and connects to it. Name resolution failures are reported apart from
+    /// the failures to connect.
+    pub async fn connect<A: ToSocketAddrs>(addrs: A) -> AppResult<Self> {
+        let addrs: Vec<SocketAddr> = lookup_host(addrs)
+            .await
+            .map_err(|e| Error::Dns(e.to_string()))?
+            .collect();
 
-    pub async fn connect<P: AsRef<Path>, A: ToSocketAddrs>(
-        key_path: P,
-        user: impl Into<String>,
-        addrs: A,
-    ) -> Result<Self, Box<dyn Error>> {
-        let key_pair = load_secret_key(key_path, None)?;
         let config = client::Config {
             inactivity_timeout: Some(Duration::from_secs(5)),
             ..<_>::default()
@@ -52,26 +133,79 @@ impl Session {
         let config = Arc::new(config);
         let sh = Client {};
 
-        let mut handle = client::connect(config, addrs, sh).await?;
-        let auth_res = handle
+        let handle = client::connect(config, &addrs[..], sh).await?;
+        Ok(Self { handle })
+    }
+
+    /// Authenticates using the given key. Returns `None` if the server rejected the key.
+    pub async fn authenticate<P: AsRef<Path>>(
+        mut self,
+        key_path: P,
+        user: impl Into<String>,
+    ) -> AppResult<Option<Session>> {
+        let key_pair = load_secret_key(key_path, None)?;
+        let auth_res = self
+            .handle
             .authenticate_publickey(user, Arc::new(key_pair))
             .await?;
 
         if !auth_res {
             log::error!("Failed to authenticate");
-            return Err(String::from("Authentication error").into());
+            return Ok(None);
         }
 
-        Ok(Self { handle })
+        Ok(Some(Session {
+            handle: self.handle,
+        }))
     }
+}
+
+impl Session {
+    pub async fn new(hostname: &str) -> AppResult<Self> {
+        Session::connect(SSH_KEY, USER, format!("{}:{}", hostname, PORT)).await
+    }
+
+    pub async fn connect<P: AsRef<Path>, A: ToSocketAddrs>(
+        key_path: P,
+        user: impl Into<String>,
+        addrs: A,
+    ) -> AppResult<Self> {
+        let handshake = Handshake::connect(addrs).await?;
+        let session = handshake.authenticate(key_path, user).await?;
+        session.ok_or_else(|| Error::Auth(String::from("Key rejected")))
+    }
+
+    /// Connects to the host, leaving the authentication to the caller.
+    pub async fn handshake(hostname: &str) -> AppResult<Handshake> {
+        Handshake::connect(format!("{}:{}", hostname, PORT)).await
+    }
+
+    /// Authenticates the connection with the default credentials.
+    pub async fn authenticate(handshake: Handshake) -> AppResult<Option<Self>> {
+        handshake.authenticate(SSH_KEY, USER).await
+    }
+
+    /// Whether the connection to the host was lost.
+    pub fn is_closed(&self) -> bool {
+        self.handle.is_closed()
+    }
+
+    pub async fn open_channel(&self) -> AppResult<Channel> {
+        let channel = self
+            .handle
+            .channel_open_session()
+            .await
+            .map_err(|e| Error::Channel(e.to_string()))?;
 
-    pub async fn open_channel(&self) -> Result<Channel, Box<dyn Error>> {
         Ok(Channel {
-            channel: self.handle.channel_open_session().await?,
+            channel,
+            stdout: vec![],
+            stderr: vec![],
+            timeout: None,
         })
     }
 
-    pub async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+    pub async fn close(&self) -> AppResult<()> {
         self.handle
             .disconnect(Disconnect::ByApplication, "", "English")
             .await?;
@@ -80,135 +214,156 @@ impl Session {
 }
 
 impl Channel {
-    pub async fn block_exec(
-        &mut self,
-        command: &str,
-    ) -> Result<(u32, String, String), Box<dyn Error>> {
-        self.channel.exec(true, command).await?;
+    /// Bounds the time spent waiting on the channel, `None` to wait forever.
+    ///
+    /// The timeout applies to the whole command with [`Channel::block_exec`], and to the wait
+    /// for every line with [`Channel::next_output`]. The channel is closed when it expires.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
 
-        let mut code = None;
-        let mut stdout = String::new();
-        let mut stderr = String::new();
+    /// Runs the command and waits for it to exit, returning its exit code, stdout and stderr.
+    ///
+    /// Fails with [`Error::Timeout`] if the command did not exit within the timeout of the
+    /// channel.
+    pub async fn block_exec(&mut self, command: &str) -> AppResult<(u32, String, String)> {
+        let Some(timeout) = self.timeout else {
+            return self.run(command).await;
+        };
 
-        loop {
-            let Some(msg) = self.channel.wait().await else {
-                break;
-            };
+        let Ok(result) = tokio::time::timeout(timeout, self.run(command)).await else {
+            let _ = self.channel.close().await;
+            return Err(Error::Timeout(timeout));
+        };
+        result
+    }
 
-            match msg {
-                ChannelMsg::Data { ref data } => {
-                    stdout.push_str(
-                        &String::from_utf8(data.iter().cloned().collect())
-                            .expect("Invalid output !"),
-                    );
-                }
+    async fn run(&mut self, command: &str) -> AppResult<(u32, String, String)> {
+        self.stream(command).await?;
 
-                ChannelMsg::ExtendedData { ref data, ext } if ext == 1 => {
-                    let error = String::from_utf8(data.iter().cloned().collect())
-                        .expect("Invalid output !");
-                    let stripped = error.strip_suffix("\n").unwrap_or(&error);
-                    stderr.push_str(stripped);
-                }
+        let mut code = None;
+        let mut stdout = String::new();
+        let mut stderr = vec![];
 
-                ChannelMsg::ExitStatus { exit_status } => {
-                    code = Some(exit_status);
+        while let Some(output) = self.read_output().await {
+            match output {
+                Output::Stdout(line) => {
+                    stdout.push_str(&line);
+                    stdout.push('\n');
                 }
-
-                _ => (),
+                Output::Stderr(line) => stderr.push(line),
+                Output::Exit(exit_status) => code = Some(exit_status),
             }
         }
 
         match code {
-            Some(value) => Ok((value, stdout, stderr)),
-            None => Err("Program did not exit cleanly !".into()),
+            Some(value) => Ok((value, stdout, stderr.join("\n"))),
+            None => Err(Error::Exec(String::from("Program did not exit cleanly !"))),
         }
     }
-}
-
-/*
-pub async fn check(&self, command: &str) -> Result<(u32, String, String), Box<dyn Error>> {
-    let mut channel = self.handle.channel_open_session().await?;
-    channel.exec(true, command).await?;
 
-    let mut code = None;
-    let mut stdout = String::new();
-    let mut stderr = String::new();
+    /// Starts the command, its output is then read line by line using [`Channel::next_output`].
+    pub async fn stream(&mut self, command: &str) -> AppResult<()> {
+        self.channel
+            .exec(true, command)
+            .await
+            .map_err(|e| Error::Exec(e.to_string()))
+    }
 
-    loop {
-        let Some(msg) = channel.wait().await else {
-            break;
+    /// Waits for the next line of output of the running command, or its exit status.
+    ///
+    /// Returns `None` once the channel is closed, after any unterminated line has been returned.
+    /// Fails with [`Error::Timeout`] if nothing was received within the timeout of the channel.
+    pub async fn next_output(&mut self) -> AppResult<Option<Output>> {
+        let Some(timeout) = self.timeout else {
+            return Ok(self.read_output().await);
         };
 
-        match msg {
-            ChannelMsg::Data { ref data } => {
-                stdout.push_str(
-                    &String::from_utf8(data.iter().cloned().collect()).expect("Invalid output !"),
-                );
-            }
-
-            ChannelMsg::ExtendedData { ref data, ext } if ext == 1 => {
-                let error =
-                    String::from_utf8(data.iter().cloned().collect()).expect("Invalid output !");
-                let stripped = error.strip_suffix("\n").unwrap_or(&error);
-                stderr.push_str(stripped);
-            }
-
-            ChannelMsg::ExitStatus { exit_status } => {
-                code = Some(exit_status);
+        match tokio::time::timeout(timeout, self.read_output()).await {
+            Ok(output) => Ok(output),
+            Err(_) => {
+                let _ = self.channel.close().await;
+                Err(Error::Timeout(timeout))
             }
-
-            _ => (),
         }
     }
 
-    Ok((code.expect("Program did not exit cleanly"), stdout, stderr))
-}
-
-pub async fn stream<S: From<(ChannelId, ChannelMsg)> + Sync + std::marker::Send + 'static>(
-    channel: &mut Channel<S>,
-    command: &str,
-) -> Result<u32, Box<dyn Error>> {
-    channel.exec(true, command).await?;
-
-    let mut code = None;
-    let mut stdout = tokio::io::stdout();
-    let mut stderr = tokio::io::stderr();
-
-    let mut buffer: Vec<u8> = vec![];
+    async fn read_output(&mut self) -> Option<Output> {
+        loop {
+            if let Some(line) = take_line(&mut self.stdout) {
+                return Some(Output::Stdout(line));
+            }
+            if let Some(line) = take_line(&mut self.stderr) {
+                return Some(Output::Stderr(line));
+            }
 
-    loop {
-        let Some(msg) = channel.wait().await else {
-            break;
-        };
+            let Some(msg) = self.channel.wait().await else {
+                break;
+            };
 
-        match msg {
-            ChannelMsg::Data { ref data } => {
-                for c in data.iter() {
-                    match c {
-                        b'\n' => {
-                            buffer.push(b'\n');
-                            stdout.write(&buffer).await?;
-                            stdout.flush().await?;
-                            buffer.clear();
-                        }
-                        _ => buffer.push(*c),
-                    }
+            match msg {
+                ChannelMsg::Data { ref data } => self.stdout.extend_from_slice(data),
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => {
+                    self.stderr.extend_from_slice(data)
                 }
+                ChannelMsg::ExitStatus { exit_status } => return Some(Output::Exit(exit_status)),
+                _ => (),
             }
+        }
 
-            ChannelMsg::ExtendedData { ref data, ext } if ext == 1 => {
-                stderr.write_all(data).await?;
-                stderr.flush().await?;
-            }
-
-            ChannelMsg::ExitStatus { exit_status } => {
-                code = Some(exit_status);
-            }
+        if !self.stdout.is_empty() {
+            return Some(Output::Stdout(lossy(self.stdout.drain(..))));
+        }
+        if !self.stderr.is_empty() {
+            return Some(Output::Stderr(lossy(self.stderr.drain(..))));
+        }
+        None
+    }
+}
 
-            _ => (),
+/// Removes the first complete line from the buffer, without its line terminator. A line longer
+/// than [`MAX_LINE`] is cut there, at the start of a character.
+fn take_line(buffer: &mut Vec<u8>) -> Option<String> {
+    let Some(end) = buffer.iter().take(MAX_LINE).position(|c| *c == b'\n') else {
+        if buffer.len() < MAX_LINE {
+            return None;
         }
+        // Continuation bytes of UTF-8 characters are 0b10xxxxxx
+        let cut = (MAX_LINE - 3..=MAX_LINE)
+            .rev()
+            .find(|index| buffer[*index] & 0xc0 != 0x80)
+            .filter(|index| *index > 0)
+            .unwrap_or(MAX_LINE);
+        return Some(lossy(buffer.drain(..cut)));
+    };
+    let mut line: Vec<u8> = buffer.drain(..=end).collect();
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
     }
+    Some(lossy(line))
+}
 
-    Ok(code.expect("Program did not exit cleanly"))
+fn lossy<I: IntoIterator<Item = u8>>(bytes: I) -> String {
+    String::from_utf8_lossy(&bytes.into_iter().collect::<Vec<u8>>()).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_long_lines() {
+        let mut buffer = b"first\r\nsecond".to_vec();
+        assert_eq!(take_line(&mut buffer).as_deref(), Some("first"));
+        assert_eq!(take_line(&mut buffer), None);
+
+        let mut buffer = vec![b'x'; MAX_LINE - 1];
+        buffer.extend_from_slice("é".as_bytes());
+        buffer.extend_from_slice(b"rest\n");
+        let line = take_line(&mut buffer).unwrap();
+        assert_eq!(line.len(), MAX_LINE - 1);
+        assert_eq!(take_line(&mut buffer).as_deref(), Some("érest"));
+        assert!(buffer.is_empty());
+    }
 }
-*/
@@ -0,0 +1,200 @@
+use crate::app::AppResult;
+use crate::config::ThemeConfig;
+use crate::error::Error;
+use ratatui::style::Color;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Colours of the interface, by what they mean rather than where they are used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Hosts up, low loads, consistent checks and added lines.
+    pub ok: Color,
+    /// Hosts connecting, loads getting high, deviating checks and warnings.
+    pub warn: Color,
+    /// Loads above the number of processors, fatal errors, alerts and removed lines.
+    pub critical: Color,
+    /// Hosts queued or stale, and anything of little interest.
+    pub muted: Color,
+    /// Latency history, groups and keys.
+    pub accent: Color,
+    /// Titles of the columns of the host table.
+    pub header: Color,
+    /// Errors reaching the hosts, which are usually transient.
+    pub network_error: Color,
+    /// Errors opening channels on a session.
+    pub channel_error: Color,
+    /// Errors of the commands run on the hosts.
+    pub remote_error: Color,
+    /// Label of the hosts an alert fires on.
+    pub alert_fg: Color,
+    pub alert_bg: Color,
+}
+
+/// Names of the built-in themes.
+pub const BUILTIN_THEMES: [&str; 4] = ["dark", "light", "high-contrast", "colour-blind"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    /// Colours of the terminal palette, meant for dark backgrounds.
+    pub fn dark() -> Self {
+        Self {
+            ok: Color::Green,
+            warn: Color::Yellow,
+            critical: Color::Red,
+            muted: Color::DarkGray,
+            accent: Color::Cyan,
+            header: Color::Reset,
+            network_error: Color::LightYellow,
+            channel_error: Color::LightMagenta,
+            remote_error: Color::Magenta,
+            alert_fg: Color::White,
+            alert_bg: Color::Red,
+        }
+    }
+
+    /// Darker colours, readable on light backgrounds.
+    pub fn light() -> Self {
+        Self {
+            ok: Color::Rgb(0, 128, 0),
+            warn: Color::Rgb(176, 112, 0),
+            critical: Color::Rgb(192, 0, 0),
+            muted: Color::Rgb(128, 128, 128),
+            accent: Color::Rgb(0, 110, 160),
+            header: Color::Reset,
+            network_error: Color::Rgb(160, 96, 0),
+            channel_error: Color::Rgb(150, 0, 150),
+            remote_error: Color::Rgb(110, 0, 110),
+            alert_fg: Color::White,
+            alert_bg: Color::Rgb(192, 0, 0),
+        }
+    }
+
+    /// Bright colours only, nothing dimmed.
+    pub fn high_contrast() -> Self {
+        Self {
+            ok: Color::LightGreen,
+            warn: Color::LightYellow,
+            critical: Color::LightRed,
+            muted: Color::White,
+            accent: Color::LightCyan,
+            header: Color::White,
+            network_error: Color::LightYellow,
+            channel_error: Color::LightMagenta,
+            remote_error: Color::LightMagenta,
+            alert_fg: Color::Black,
+            alert_bg: Color::LightRed,
+        }
+    }
+
+    /// Okabe-Ito palette, telling states apart without relying on red and green.
+    pub fn colour_blind() -> Self {
+        Self {
+            ok: Color::Rgb(0, 114, 178),
+            warn: Color::Rgb(230, 159, 0),
+            critical: Color::Rgb(213, 94, 0),
+            muted: Color::Rgb(128, 128, 128),
+            accent: Color::Rgb(86, 180, 233),
+            header: Color::Reset,
+            network_error: Color::Rgb(240, 228, 66),
+            channel_error: Color::Rgb(204, 121, 167),
+            remote_error: Color::Rgb(204, 121, 167),
+            alert_fg: Color::Black,
+            alert_bg: Color::Rgb(230, 159, 0),
+        }
+    }
+
+    /// The colours of the terminal, for `NO_COLOR`. States are still told apart by their text and
+    /// by the bold, dimmed and reversed styles.
+    pub fn no_color() -> Self {
+        Self {
+            ok: Color::Reset,
+            warn: Color::Reset,
+            critical: Color::Reset,
+            muted: Color::Reset,
+            accent: Color::Reset,
+            header: Color::Reset,
+            network_error: Color::Reset,
+            channel_error: Color::Reset,
+            remote_error: Color::Reset,
+            alert_fg: Color::Reset,
+            alert_bg: Color::Reset,
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "colour-blind" | "color-blind" => Some(Self::colour_blind()),
+            _ => None,
+        }
+    }
+
+    /// Theme of the given name, user-defined themes taking precedence over the built-in ones.
+    /// Defaults to the dark theme, and to no colours at all when `NO_COLOR` is set.
+    pub fn from_config(
+        name: Option<&str>,
+        themes: &BTreeMap<String, ThemeConfig>,
+    ) -> AppResult<Self> {
+        // Resolved anyway, so that a mistake in the configuration is not hidden by `NO_COLOR`
+        let theme = Self::resolve(name.unwrap_or("dark"), themes)?;
+
+        // See https://no-color.org
+        if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Ok(Self::no_color());
+        }
+
+        Ok(theme)
+    }
+
+    fn resolve(name: &str, themes: &BTreeMap<String, ThemeConfig>) -> AppResult<Self> {
+        let Some(config) = themes.get(name) else {
+            return Self::builtin(name).ok_or_else(|| {
+                Error::Parse(format!(
+                    "Unknown theme: {} (built-in themes: {})",
+                    name,
+                    BUILTIN_THEMES.join(", ")
+                ))
+            });
+        };
+
+        // User-defined themes only build on built-in ones, so that they cannot loop
+        let base = config.base.as_deref().unwrap_or("dark");
+        let mut theme = Self::builtin(base)
+            .ok_or_else(|| Error::Parse(format!("Unknown base theme of {}: {}", name, base)))?;
+        for (field, value) in config.colors.iter() {
+            let color = Color::from_str(value)
+                .map_err(|_| Error::Parse(format!("Invalid colour for {}: {}", field, value)))?;
+            *theme.field_mut(field).ok_or_else(|| {
+                Error::Parse(format!("Unknown colour of theme {}: {}", name, field))
+            })? = color;
+        }
+
+        Ok(theme)
+    }
+
+    /// Colour of the given name in the configuration file.
+    fn field_mut(&mut self, name: &str) -> Option<&mut Color> {
+        match name {
+            "ok" => Some(&mut self.ok),
+            "warn" => Some(&mut self.warn),
+            "critical" => Some(&mut self.critical),
+            "muted" => Some(&mut self.muted),
+            "accent" => Some(&mut self.accent),
+            "header" => Some(&mut self.header),
+            "network-error" => Some(&mut self.network_error),
+            "channel-error" => Some(&mut self.channel_error),
+            "remote-error" => Some(&mut self.remote_error),
+            "alert-fg" => Some(&mut self.alert_fg),
+            "alert-bg" => Some(&mut self.alert_bg),
+            _ => None,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::app::{App, AppResult};
-use crate::event::Event;
+use crate::error::Error;
+use crate::event::{self, Event, EventReceiver, EventSender};
 use crate::ui;
 use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
@@ -7,7 +8,6 @@ use ratatui::backend::Backend;
 use ratatui::Terminal;
 use std::io;
 use std::panic;
-use tokio::sync::mpsc;
 
 /// Representation of a terminal user interface.
 ///
@@ -19,15 +19,15 @@ pub struct Tui<B: Backend> {
     terminal: Terminal<B>,
     /// Sender end of the Tui's channel. Never actually used by this struct but a clone of it is
     /// passed to any new EventHandlers
-    sender: mpsc::UnboundedSender<Event>,
+    sender: EventSender,
     /// Receiving end of the Tui's channel, all events are read from here
-    receiver: mpsc::UnboundedReceiver<Event>,
+    receiver: EventReceiver,
 }
 
 impl<B: Backend> Tui<B> {
     /// Constructs a new instance of [`Tui`].
     pub fn new(terminal: Terminal<B>) -> Self {
-        let (sender, receiver) = mpsc::unbounded_channel();
+        let (sender, receiver) = event::channel(event::EVENT_CAPACITY);
         Self {
             terminal,
             sender,
@@ -83,7 +83,7 @@ impl<B: Backend> Tui<B> {
         Ok(())
     }
 
-    pub fn channel(&self) -> mpsc::UnboundedSender<Event> {
+    pub fn channel(&self) -> EventSender {
         self.sender.clone()
     }
 
@@ -91,9 +91,6 @@ impl<B: Backend> Tui<B> {
         self.receiver
             .recv()
             .await
-            .ok_or(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "This is an IO error",
-            )))
+            .ok_or(Error::Io(String::from("Event channel closed")))
     }
 }
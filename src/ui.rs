@@ -1,12 +1,45 @@
 use ratatui::{
-    layout::Alignment,
     prelude::*,
     style::{Color, Style},
     widgets::*,
     Frame,
 };
 
-use crate::app::{App, HostState};
+use crate::alert::Alerts;
+use crate::app::{App, Areas, HostState, LoadAverage, Replay, SortColumn, View, HOST_STATES};
+use crate::command::{CommandRun, CommandStatus, DiffLine, OutputLine};
+use crate::drift::DriftRun;
+use crate::error::{Error, ErrorClass};
+use crate::history::History;
+use crate::keymap::{KeyAction, KeyContext, Keymap};
+use crate::nodes;
+use crate::theme::Theme;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+/// Number of lines shown per host when following the output of a command.
+const TAIL_LINES: usize = 5;
+
+/// Number of latencies drawn in the history column.
+const SPARKLINE_WIDTH: usize = 20;
+
+/// Normalized load from which hosts are shown as busy.
+const NORMALIZED_LOAD_WARN: f64 = 0.7;
+
+/// Normalized load from which hosts are shown as overloaded, with as many tasks as processors.
+const NORMALIZED_LOAD_HIGH: f64 = 1.0;
+
+/// Number of hosts listed as the busiest in the overview.
+const OVERVIEW_BUSIEST: usize = 5;
+
+/// Width of the help overlay.
+const HELP_WIDTH: u16 = 90;
+
+/// Width of the popup showing the details of a host.
+const POPUP_WIDTH: u16 = 72;
+
+/// Largest number of firing rules listed at once in the alerts panel.
+const MAX_ALERT_LINES: usize = 10;
 
 /// Renders the user interface widgets.
 pub fn render(app: &mut App, frame: &mut Frame) {
@@ -15,31 +48,1301 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
     // - https://github.com/ratatui-org/ratatui/tree/master/examples
 
-    let header = Row::new(vec!["host", "load"]);
-    let widths = [Constraint::Percentage(25), Constraint::Fill(1)];
+    let command_height = match app.command.is_some() || app.drift.is_some() {
+        true => Constraint::Percentage(50),
+        false => Constraint::Length(0),
+    };
+    let [tabs_area, view_area, command_area, status_area, prompt_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Fill(1),
+        command_height,
+        Constraint::Length(app.replay.is_some() as u16),
+        Constraint::Length(app.prompt.is_some() as u16),
+    ])
+    .areas(frame.size());
 
-    let content: Vec<Row> = app
+    let titles = tab_titles(app);
+    app.areas = Areas {
+        tabs: tab_areas(tabs_area, &titles),
+        view: view_area,
+        results: command_area,
+        ..Areas::default()
+    };
+    frame.render_widget(
+        Tabs::new(titles)
+            .select(app.view.index())
+            .highlight_style(Style::new().fg(app.theme.accent).bold().reversed()),
+        tabs_area,
+    );
+
+    match app.view {
+        View::Overview => frame.render_widget(overview(app), view_area),
+        View::Hosts => render_hosts(app, frame, view_area),
+        View::Lustre => frame.render_widget(lustre(app), view_area),
+        View::Alerts => {
+            frame.render_widget(alerts_view(&app.theme, &app.keymap, &app.alerts), view_area)
+        }
+        View::Log => {
+            app.areas.log = view_area;
+            let hints = hints(
+                &app.keymap,
+                &[(
+                    &[
+                        KeyAction::SelectNext,
+                        KeyAction::SelectPrevious,
+                        KeyAction::LogScrollUp,
+                        KeyAction::LogScrollDown,
+                    ],
+                    "scroll",
+                )],
+            );
+            frame.render_widget(
+                log_pane(&app.theme, &app.history, view_area.height, &hints),
+                view_area,
+            );
+        }
+    }
+
+    let theme = &app.theme;
+    let keymap = &app.keymap;
+    if let Some(run) = app.command.as_mut() {
+        frame.render_widget(command_results(theme, keymap, run), command_area);
+    }
+
+    if let Some(run) = app.drift.as_mut() {
+        frame.render_widget(drift_report(theme, keymap, run), command_area);
+    }
+
+    if let Some(replay) = app.replay.as_ref() {
+        frame.render_widget(replay_status(keymap, replay), status_area);
+    }
+
+    if let Some(prompt) = app.prompt.as_ref() {
+        let label = prompt.kind.label();
+        frame.render_widget(
+            Paragraph::new(format!("{}{}", label, prompt.text)),
+            prompt_area,
+        );
+        frame.set_cursor(
+            prompt_area.x + (label.len() + prompt.text.chars().count()) as u16,
+            prompt_area.y,
+        );
+    }
+
+    if let Some(scroll) = app.help {
+        let lines = help(theme, keymap);
+        let hints = hints(
+            keymap,
+            &[
+                (
+                    &[KeyAction::SelectNext, KeyAction::SelectPrevious],
+                    "scroll",
+                ),
+                (&[KeyAction::Help], "close"),
+            ],
+        );
+        let area = centered(frame.size(), HELP_WIDTH, lines.len() as u16 + 2);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(format!(" help ({}) ", hints)),
+                )
+                .scroll((scroll, 0)),
+            area,
+        );
+    }
+
+    if let Some(name) = app.popup.as_ref() {
+        let lines = host_details(app, name);
+        let area = centered(frame.size(), POPUP_WIDTH, lines.len() as u16 + 2);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", name)),
+            ),
+            area,
+        );
+    }
+}
+
+/// Titles of the tabs, numbered by the key switching to them.
+fn tab_titles(app: &App) -> Vec<String> {
+    View::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, view)| match view {
+            View::Alerts => match app.alerts.firing_rules().len() {
+                0 => format!("{} {}", index + 1, view.title()),
+                firing => format!("{} {} ({})", index + 1, view.title(), firing),
+            },
+            _ => format!("{} {}", index + 1, view.title()),
+        })
+        .collect()
+}
+
+/// Position of the titles drawn by [`Tabs`], padded by a space on both sides and separated by a
+/// divider.
+fn tab_areas(area: Rect, titles: &[String]) -> Vec<(Rect, View)> {
+    let mut x = area.x;
+    let mut areas = vec![];
+    for (title, view) in titles.iter().zip(View::ALL) {
+        let width = title.chars().count() as u16 + 2;
+        areas.push((Rect::new(x, area.y, width, 1).intersection(area), view));
+        x = x.saturating_add(width + 1);
+    }
+    areas
+}
+
+/// Summary of the hosts: how many are in each state, how loaded they are and what fails.
+fn overview(app: &App) -> Paragraph<'static> {
+    let theme = &app.theme;
+    let label = |text: &str| Span::styled(format!("{:<14}", text), Style::new().bold());
+
+    let mut states = HashMap::<&str, Vec<&str>>::new();
+    for (name, host) in app.hosts.iter() {
+        states.entry(app.state_name(host)).or_default().push(name);
+    }
+    let mut counts = vec![
+        label("hosts"),
+        Span::raw(format!("{} total", app.hosts.len())),
+    ];
+    for state in HOST_STATES {
+        let Some(hosts) = states.get(state) else {
+            continue;
+        };
+        let color = match state {
+            "up" => theme.ok,
+            "connecting" | "authenticating" | "degraded" => theme.warn,
+            "backoff" | "down" => theme.critical,
+            _ => theme.muted,
+        };
+        counts.push(Span::styled(
+            format!("  {} {}", hosts.len(), state),
+            Style::new().fg(color),
+        ));
+    }
+    let mut lines = vec![Line::from(counts), Line::raw("")];
+
+    // Hosts up, from the most loaded to the least
+    let mut loads: Vec<(&str, f64)> = app
+        .hosts
+        .iter()
+        .filter_map(|(name, host)| Some((name.as_str(), host.load()?.normalized(host.cpus()?)?)))
+        .collect();
+    loads.sort_by(|(a, x), (b, y)| y.total_cmp(x).then_with(|| a.cmp(b)));
+    if let Some((busiest, max)) = loads.first() {
+        let mean = loads.iter().map(|(_, load)| load).sum::<f64>() / loads.len() as f64;
+        let overloaded: Vec<&str> = loads
+            .iter()
+            .filter(|(_, load)| *load >= NORMALIZED_LOAD_HIGH)
+            .map(|(name, _)| *name)
+            .collect();
+        let mut line = vec![
+            label("load/cpu"),
+            Span::styled(
+                format!("mean {:.2}", mean),
+                Style::new().fg(load_color(theme, mean)),
+            ),
+            Span::styled(
+                format!("  max {:.2} ({})", max, busiest),
+                Style::new().fg(load_color(theme, *max)),
+            ),
+        ];
+        if !overloaded.is_empty() {
+            line.push(Span::styled(
+                format!(
+                    "  overloaded {} ({})",
+                    nodes::fold(&overloaded),
+                    overloaded.len()
+                ),
+                Style::new().fg(theme.critical),
+            ));
+        }
+        lines.push(Line::from(line));
+        lines.push(Line::from(vec![
+            label("busiest"),
+            Span::raw(
+                loads
+                    .iter()
+                    .take(OVERVIEW_BUSIEST)
+                    .map(|(name, load)| format!("{} {:.2}", name, load))
+                    .collect::<Vec<_>>()
+                    .join("  "),
+            ),
+        ]));
+    }
+
+    let memory: Vec<(&str, f64)> = app
         .hosts
         .iter()
-        .map(|(host, status)| match status {
-            HostState::Connecting => Row::new(vec![
-                Cell::from(host.as_str()).style(Style::default().fg(Color::Yellow)),
-                Cell::from("Connecting ...").style(Style::default()),
-            ]),
-            HostState::Up(content) => Row::new(vec![
-                Cell::from(host.as_str()).style(Style::default().fg(Color::Green)),
-                Cell::from(content.as_str()).style(Style::default()),
-            ]),
-            HostState::Down(content) => Row::new(vec![
-                Cell::from(host.as_str()).style(Style::default().fg(Color::Red)),
-                Cell::from(content.as_str()).style(Style::default()),
-            ]),
+        .filter_map(|(name, host)| match host.memory {
+            Some((total, available)) if total > 0 => Some((
+                name.as_str(),
+                total.saturating_sub(available) as f64 / total as f64,
+            )),
+            _ => None,
+        })
+        .collect();
+    if let Some((name, max)) = memory
+        .iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .copied()
+    {
+        let mean = memory.iter().map(|(_, used)| used).sum::<f64>() / memory.len() as f64;
+        lines.push(Line::from(vec![
+            label("memory used"),
+            Span::raw(format!(
+                "mean {:.0}%  max {:.0}% ({})",
+                mean * 100.0,
+                max * 100.0,
+                name
+            )),
+        ]));
+    }
+
+    let mut rtts: Vec<(&str, Duration)> = app
+        .hosts
+        .iter()
+        .filter_map(|(name, host)| Some((name.as_str(), *host.latency.back()?)))
+        .collect();
+    rtts.sort_by_key(|(_, rtt)| *rtt);
+    if let Some((name, max)) = rtts.last() {
+        lines.push(Line::from(vec![
+            label("rtt"),
+            Span::raw(format!(
+                "median {}  max {} ({})",
+                format_latency(&rtts[rtts.len() / 2].1),
+                format_latency(max),
+                name
+            )),
+        ]));
+    }
+
+    let mut errors = BTreeMap::<ErrorClass, Vec<&str>>::new();
+    for (name, host) in app.hosts.iter() {
+        if let Some(error) = host.state.error() {
+            errors.entry(error.class()).or_default().push(name);
+        }
+    }
+    if !errors.is_empty() {
+        lines.push(Line::raw(""));
+    }
+    for (class, hosts) in errors.iter() {
+        lines.push(Line::from(vec![
+            label(if lines.last() == Some(&Line::raw("")) {
+                "errors"
+            } else {
+                ""
+            }),
+            Span::styled(
+                format!("{}: {} ({})", class.name(), nodes::fold(hosts), hosts.len()),
+                Style::new().fg(error_color(theme, *class)),
+            ),
+        ]));
+    }
+
+    let firing = app.alerts.firing_rules();
+    if !app.alerts.rules.is_empty() {
+        lines.push(Line::raw(""));
+        let style = match firing.is_empty() {
+            true => Style::new().fg(theme.ok),
+            false => Style::new().fg(theme.critical),
+        };
+        lines.push(Line::from(vec![
+            label("alerts"),
+            Span::styled(
+                format!("{} firing / {} rules", firing.len(), app.alerts.rules.len()),
+                style,
+            ),
+        ]));
+    }
+
+    Paragraph::new(lines).block(Block::default().borders(Borders::TOP).title(" overview "))
+}
+
+/// Lustre filesystems mounted on the hosts, and the hosts whose Lustre modules are not healthy,
+/// as reported in their inventory.
+fn lustre(app: &App) -> Paragraph<'static> {
+    let theme = &app.theme;
+    let mut mounts = BTreeMap::<&str, Vec<&str>>::new();
+    let mut health = BTreeMap::<&str, Vec<&str>>::new();
+    let mut without = vec![];
+    let mut unknown = vec![];
+    for name in app.host_names() {
+        let Some((name, host)) = app.hosts.get_key_value(&name) else {
+            continue;
+        };
+        let Some(inventory) = host.inventory.as_ref() else {
+            unknown.push(name.as_str());
+            continue;
+        };
+        if inventory.lustre.is_none() {
+            without.push(name.as_str());
+        }
+        for mount in inventory.lustre_mounts() {
+            mounts.entry(mount).or_default().push(name);
+        }
+        if let Some(status) = inventory.lustre_health.as_deref() {
+            health.entry(status).or_default().push(name);
+        }
+    }
+
+    let hosts = |hosts: &[&str]| format!("{} ({})", nodes::fold(hosts), hosts.len());
+    let mut lines = vec![];
+    for (mount, mounted) in mounts.iter() {
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:<32} ", mount), Style::new().bold()),
+            Span::raw(hosts(mounted)),
+        ]));
+    }
+    if mounts.is_empty() {
+        lines.push(Line::styled(
+            "No Lustre filesystem mounted",
+            Style::new().fg(theme.muted),
+        ));
+    }
+
+    lines.push(Line::raw(""));
+    for (status, reporting) in health.iter() {
+        let color = match *status {
+            "healthy" => theme.ok,
+            _ => theme.critical,
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:<32} ", status), Style::new().fg(color)),
+            Span::raw(hosts(reporting)),
+        ]));
+    }
+    if !without.is_empty() {
+        lines.push(Line::styled(
+            format!("{:<32} {}", "no Lustre mount", hosts(&without)),
+            Style::new().fg(theme.muted),
+        ));
+    }
+    if !unknown.is_empty() {
+        lines.push(Line::styled(
+            format!("{:<32} {}", "inventory not collected", hosts(&unknown)),
+            Style::new().fg(theme.muted),
+        ));
+    }
+
+    Paragraph::new(lines).block(Block::default().borders(Borders::TOP).title(" lustre "))
+}
+
+/// Every alert rule, with the hosts it fires on and the hosts it is about to fire on.
+fn alerts_view(theme: &Theme, keymap: &Keymap, alerts: &Alerts) -> Paragraph<'static> {
+    let firing = alerts.firing_rules();
+    let mut lines = vec![];
+    for (index, rule) in alerts.rules.iter().enumerate() {
+        let firing = firing
+            .iter()
+            .find(|(firing, _, _)| std::ptr::eq(*firing, rule));
+        let style = match firing {
+            Some(_) => Style::new().fg(theme.critical).bold(),
+            None => Style::new().fg(theme.ok).bold(),
+        };
+        lines.push(Line::styled(rule.name.clone(), style));
+        if rule.name != rule.text {
+            lines.push(Line::raw(format!("  rule     {}", rule.text)));
+        }
+        lines.push(Line::raw(format!(
+            "  clears   after {}",
+            format_duration(rule.clear)
+        )));
+        if let Some(hook) = rule.hook.as_ref() {
+            lines.push(Line::raw(format!("  hook     {}", hook)));
+        }
+        if let Some((_, hosts, since)) = firing {
+            lines.push(Line::styled(
+                format!(
+                    "  firing   {} ({}) for {}",
+                    nodes::fold(hosts),
+                    hosts.len(),
+                    format_duration(since.elapsed())
+                ),
+                Style::new().fg(theme.critical),
+            ));
+        }
+        let pending = alerts.pending_hosts(index);
+        if !pending.is_empty() {
+            lines.push(Line::styled(
+                format!("  pending  {} ({})", nodes::fold(&pending), pending.len()),
+                Style::new().fg(theme.warn),
+            ));
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Line::styled(
+            "No alert rule configured",
+            Style::new().fg(theme.muted),
+        ));
+    }
+
+    let title = format!(
+        " alerts ({} firing / {} rules) {} ",
+        firing.len(),
+        alerts.rules.len(),
+        hints(
+            keymap,
+            &[(
+                &[KeyAction::SelectNext, KeyAction::SelectPrevious],
+                "scroll"
+            )]
+        )
+    );
+    Paragraph::new(lines)
+        .block(Block::default().borders(Borders::TOP).title(title))
+        .scroll((alerts.scroll, 0))
+}
+
+/// Renders the table of the hosts, along with the alerts panel and log pane when shown.
+fn render_hosts(app: &mut App, frame: &mut Frame, area: Rect) {
+    // Arrow next to the title of the column the rows are sorted by
+    let arrow = |column| match app.sort {
+        _ if app.group_by.is_some() => "",
+        (sorted, false) if sorted == column => " ▲",
+        (sorted, true) if sorted == column => " ▼",
+        _ => "",
+    };
+    // Filter or grouping, along with the key changing it
+    let tag = |label: String, action: KeyAction, hint: &str| match hints(
+        &app.keymap,
+        &[(&[action], hint)],
+    ) {
+        hints if hints.is_empty() => format!(" [{}]", label),
+        hints => format!(" [{}, {}]", label, hints),
+    };
+    let mut host_header = format!("host{}", arrow(SortColumn::Host));
+    if let Some(class) = app.error_filter {
+        let label = format!("{} errors", class.name());
+        host_header.push_str(&tag(label, KeyAction::ErrorFilter, "next"));
+    }
+    if let Some((field, value)) = app.fact_filter.as_ref() {
+        let label = format!("{}={}", field, value.as_deref().unwrap_or("unknown"));
+        host_header.push_str(&tag(label, KeyAction::ClearFilter, "clear"));
+    }
+    if let Some(field) = app.group_by {
+        host_header.push_str(&tag(format!("by {}", field), KeyAction::Open, "filter"));
+    }
+    let columns = [
+        (host_header, Some(SortColumn::Host)),
+        (String::from("load"), Some(SortColumn::Load)),
+        (String::from("load/cpu"), Some(SortColumn::LoadPerCpu)),
+        (String::from("memory"), Some(SortColumn::Memory)),
+        (String::from("age"), Some(SortColumn::Age)),
+        (String::from("rtt"), Some(SortColumn::Rtt)),
+        (String::from("rtt history"), None),
+        (String::from("handshake"), Some(SortColumn::Handshake)),
+    ];
+    let header = Row::new(columns.iter().map(|(title, column)| match column {
+        Some(column) if *column != SortColumn::Host => format!("{}{}", title, arrow(*column)),
+        _ => title.clone(),
+    }));
+    let widths = [
+        Constraint::Percentage(25),
+        Constraint::Fill(1),
+        Constraint::Length(12),
+        Constraint::Length(16),
+        Constraint::Length(6),
+        Constraint::Length(8),
+        Constraint::Length(SPARKLINE_WIDTH as u16),
+        // Room for the sort arrow
+        Constraint::Length(11),
+    ];
+
+    let theme = &app.theme;
+    let content: Vec<Row> = app
+        .rows()
+        .into_iter()
+        .map(|hosts| {
+            let marker = match hosts.iter().all(|host| app.marked.contains(host)) {
+                true => "*",
+                false => " ",
+            };
+            let host = &app.hosts[&hosts[0]];
+            let label = match &hosts[..] {
+                [name] => format!("{} {}", marker, name),
+                _ => format!("{} {} ({})", marker, nodes::fold(&hosts), hosts.len()),
+            };
+            let stale = app.is_stale(host);
+            let (color, status) = match &host.state {
+                _ if app.group_by.is_some() => {
+                    let field = app.group_by.unwrap_or_default();
+                    let value = host.fact(field).unwrap_or_else(|| String::from("unknown"));
+                    (theme.accent, format!("{}: {}", field, value))
+                }
+                HostState::Up(content) if content.is_empty() => {
+                    (theme.muted, String::from("Waiting for a sample ..."))
+                }
+                HostState::Up(content) if stale => (theme.muted, format!("[stale] {}", content)),
+                HostState::Queued => (theme.muted, String::from("Queued ...")),
+                HostState::Connecting => (theme.warn, String::from("Connecting ...")),
+                HostState::Authenticating => (theme.warn, String::from("Authenticating ...")),
+                HostState::Up(content) => (theme.ok, content.clone()),
+                HostState::Degraded(error) => {
+                    (error_color(theme, error.class()), error.to_string())
+                }
+                HostState::Backoff(delay, error) => (
+                    error_color(theme, error.class()),
+                    format!("{} (retrying in {}s)", error, delay.as_secs()),
+                ),
+                HostState::Down(error) => (
+                    error_color(theme, error.class()),
+                    format!("{} (gave up)", error),
+                ),
+            };
+            let normalized = match &host.state {
+                HostState::Up(load) => load
+                    .parse::<LoadAverage>()
+                    .ok()
+                    .zip(host.cpus())
+                    .and_then(|(load, cpus)| Some((load.normalized(cpus)?, cpus))),
+                _ => None,
+            };
+            let normalized = match normalized {
+                Some((load, cpus)) => Cell::from(format!("{:.2} ({})", load, cpus))
+                    .style(Style::default().fg(load_color(theme, load))),
+                None => Cell::from(""),
+            };
+            let memory = match (&host.state, host.memory) {
+                (HostState::Up(_), Some((total, available))) => format_memory(total, available),
+                _ => String::new(),
+            };
+
+            let age = format!("{}s", host.updated.elapsed().as_secs());
+            let rtt = host.latency.back().map(format_latency).unwrap_or_default();
+            let handshake = host
+                .handshake
+                .as_ref()
+                .map(format_latency)
+                .unwrap_or_default();
+
+            let label_style = match hosts.iter().any(|host| app.alerts.firing_count(host) > 0) {
+                true => alert_style(theme),
+                false => Style::default().fg(color),
+            };
+
+            let row_style = match stale {
+                true => Style::default().dim(),
+                false => Style::default(),
+            };
+
+            Row::new(vec![
+                Cell::from(label).style(label_style),
+                Cell::from(status).style(Style::default()),
+                normalized,
+                Cell::from(memory).style(Style::default()),
+                Cell::from(age).style(Style::default()),
+                Cell::from(rtt).style(Style::default()),
+                Cell::from(sparkline(&host.latency)).style(Style::default().fg(theme.accent)),
+                Cell::from(handshake).style(Style::default()),
+            ])
+            .style(row_style)
         })
         .collect();
 
     let load_table = Table::new(content, widths)
         .column_spacing(1)
-        .header(header.style(Style::new().bold()));
+        .header(header.style(Style::new().fg(theme.header).bold()))
+        .highlight_style(Style::new().reversed());
+
+    let log_height = match app.history.visible {
+        true => Constraint::Percentage(30),
+        false => Constraint::Length(0),
+    };
+    // Border, and one line per firing rule
+    let alerts_height = match app.alerts.visible {
+        true => {
+            Constraint::Length(app.alerts.firing_rules().len().clamp(1, MAX_ALERT_LINES) as u16 + 1)
+        }
+        false => Constraint::Length(0),
+    };
+    let [table_area, alerts_area, log_area] =
+        Layout::vertical([Constraint::Fill(1), alerts_height, log_height]).areas(area);
+
+    frame.render_stateful_widget(load_table, table_area, &mut app.table_state);
+
+    // Same layout as the one the table uses for its columns
+    let header_cells = Layout::horizontal(widths).spacing(1).split(Rect::new(
+        table_area.x,
+        table_area.y,
+        table_area.width,
+        1,
+    ));
+    app.areas.table = table_area;
+    app.areas.header = header_cells
+        .iter()
+        .zip(columns.iter())
+        .map(|(cell, (_, column))| (*cell, *column))
+        .collect();
+    app.areas.log = log_area;
+
+    if app.alerts.visible {
+        frame.render_widget(
+            alerts_panel(&app.theme, &app.keymap, &app.alerts),
+            alerts_area,
+        );
+    }
+
+    if app.history.visible {
+        let hints = hints(
+            &app.keymap,
+            &[
+                (&[KeyAction::Log], "close"),
+                (&[KeyAction::LogHosts], "selected host"),
+                (
+                    &[KeyAction::LogScrollUp, KeyAction::LogScrollDown],
+                    "scroll",
+                ),
+            ],
+        );
+        frame.render_widget(
+            log_pane(&app.theme, &app.history, log_area.height, &hints),
+            log_area,
+        );
+    }
+}
+
+/// Actions sharing a hint, and what they do.
+type Hint<'a> = (&'a [KeyAction], &'a str);
+
+/// Keys of the other modes, listed after the host table in the help.
+static MODE_BINDINGS: &[(&str, &[Hint])] = &[
+    (
+        "Command results",
+        &[
+            (
+                &[KeyAction::ToggleDiff],
+                "Show the differences with the most common output",
+            ),
+            (
+                &[KeyAction::ToggleGrouping],
+                "Group the hosts with identical output",
+            ),
+            (
+                &[KeyAction::SelectNext, KeyAction::SelectPrevious],
+                "Scroll",
+            ),
+            (&[KeyAction::Prompt], "Run another command"),
+            (&[KeyAction::Quit], "Close, stopping the command"),
+        ],
+    ),
+    (
+        "Drift report",
+        &[
+            (&[KeyAction::Rerun], "Run the checks again"),
+            (
+                &[KeyAction::SelectNext, KeyAction::SelectPrevious],
+                "Scroll",
+            ),
+            (&[KeyAction::Quit], "Close, stopping the checks"),
+        ],
+    ),
+    (
+        "Alerts and log tabs",
+        &[
+            (
+                &[KeyAction::SelectNext, KeyAction::SelectPrevious],
+                "Scroll",
+            ),
+            (
+                &[KeyAction::LogScrollUp, KeyAction::LogScrollDown],
+                "Scroll by a page",
+            ),
+        ],
+    ),
+];
+
+/// Keys that cannot be remapped, listed last in the help.
+static FIXED_BINDINGS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Views",
+        &[("1 - 5", "Switch to the tab of the given number")],
+    ),
+    (
+        "Prompt",
+        &[
+            ("enter", "Run the command, or add or remove the hosts"),
+            ("esc", "Cancel"),
+        ],
+    ),
+    (
+        "Mouse",
+        &[
+            (
+                "click",
+                "Switch tabs, highlight a row, or sort by the column of a header",
+            ),
+            ("double-click", "Show the details of the host"),
+            (
+                "wheel",
+                "Scroll the table, log, alerts or results under the pointer",
+            ),
+        ],
+    ),
+    (
+        "Anywhere",
+        &[
+            ("esc", "Close the help, details, results or drift report"),
+            ("ctrl-c", "Quit"),
+        ],
+    ),
+];
+
+/// Lists the keys bound to every action, then the keys of the other modes.
+fn help(theme: &Theme, keymap: &Keymap) -> Vec<Line<'static>> {
+    let section = |title: &str| Line::styled(title.to_string(), Style::new().bold());
+    let binding = |keys: &str, description: &str| {
+        Line::from(vec![
+            Span::styled(format!("  {:<24} ", keys), Style::new().fg(theme.accent)),
+            Span::raw(description.to_string()),
+        ])
+    };
+
+    let mut lines = vec![section("Host table")];
+    for action in KeyAction::ALL {
+        let keys = keymap.keys(action);
+        if action.context() == KeyContext::Main && !keys.is_empty() {
+            lines.push(binding(&keys.join(", "), action.description()));
+        }
+    }
+    for (title, bindings) in MODE_BINDINGS {
+        lines.push(Line::raw(""));
+        lines.push(section(title));
+        for (actions, description) in bindings.iter() {
+            let keys: Vec<String> = actions
+                .iter()
+                .flat_map(|action| keymap.keys(*action))
+                .collect();
+            if !keys.is_empty() {
+                lines.push(binding(&keys.join(", "), description));
+            }
+        }
+    }
+    for (title, bindings) in FIXED_BINDINGS {
+        lines.push(Line::raw(""));
+        lines.push(section(title));
+        lines.extend(
+            bindings
+                .iter()
+                .map(|(keys, description)| binding(keys, description)),
+        );
+    }
+    lines
+}
+
+/// Hints of the keys bound to the actions, e.g. `j/k: scroll  q: close`. Unbound actions are left
+/// out.
+fn hints(keymap: &Keymap, hints: &[Hint]) -> String {
+    hints
+        .iter()
+        .filter_map(|(actions, hint)| {
+            keymap
+                .hint(actions)
+                .map(|keys| format!("{}: {}", keys, hint))
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Area of the given size in the middle of `area`, shrunk to fit in it.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Group and inventory of the host, shown in its popup.
+fn host_details(app: &App, name: &str) -> Vec<Line<'static>> {
+    let Some(host) = app.hosts.get(name) else {
+        return vec![Line::raw("Unknown host")];
+    };
+
+    let mut lines = vec![Line::raw(format!("{:<10} {}", "group", host.group))];
+    let Some(inventory) = host.inventory.as_ref() else {
+        lines.push(Line::styled(
+            "Inventory not collected yet",
+            Style::new().fg(app.theme.muted),
+        ));
+        return lines;
+    };
+
+    for (field, value) in inventory.fields() {
+        let value = match (field, value) {
+            (_, None) => String::from("unknown"),
+            ("memory", Some(_)) => inventory
+                .memory
+                .map(|memory| format!("{:.1}G", memory as f64 / (1024.0 * 1024.0)))
+                .unwrap_or_default(),
+            // Computed from the boot time to stay current
+            ("uptime", Some(value)) => inventory.up_for().map(format_uptime).unwrap_or(value),
+            ("boot", Some(value)) => inventory.boot.map(format_date).unwrap_or(value),
+            (_, Some(value)) => value,
+        };
+        lines.push(Line::raw(format!("{:<10} {}", field, value)));
+    }
+    lines
+}
+
+/// Renders the outputs of a command, grouping the hosts with identical outputs.
+///
+/// In diff mode, every group is compared with the most common output. When not grouped, the
+/// latest lines of every host are shown instead, to follow long running commands.
+fn command_results(theme: &Theme, keymap: &Keymap, run: &mut CommandRun) -> Paragraph<'static> {
+    let lines = match run.grouped {
+        true => grouped_output(theme, run),
+        false => host_output(theme, run),
+    };
+
+    let title = format!(
+        " {} ({}/{} done) {} ",
+        run.command,
+        run.results.len() - run.running(),
+        run.results.len(),
+        hints(
+            keymap,
+            &[
+                (&[KeyAction::ToggleDiff], "diff"),
+                (&[KeyAction::ToggleGrouping], "group"),
+                (&[KeyAction::Quit], "close"),
+            ]
+        )
+    );
+
+    Paragraph::new(lines)
+        .block(Block::default().borders(Borders::TOP).title(title))
+        .scroll((run.scroll, 0))
+}
+
+/// Latest lines of output of every host, prefixed by the hostname.
+fn host_output(theme: &Theme, run: &CommandRun) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    for (host, result) in run.results.iter() {
+        let tail = result.lines.len().saturating_sub(TAIL_LINES);
+        for line in result.lines.iter().skip(tail) {
+            let style = match line {
+                OutputLine::Stdout(_) => Style::new(),
+                OutputLine::Stderr(_) => Style::new().fg(theme.critical),
+            };
+            lines.push(Line::styled(format!("{}: {}", host, line.text()), style));
+        }
+
+        match &result.status {
+            CommandStatus::Running => (),
+            CommandStatus::Exited(code) => lines.push(Line::styled(
+                format!("{}: exited with code {}", host, code),
+                Style::new().bold(),
+            )),
+            CommandStatus::Failed(error) => lines.push(Line::styled(
+                format!("{}: {}", host, error),
+                Style::new().fg(theme.critical).bold(),
+            )),
+        }
+    }
+    lines
+}
+
+/// Outputs of the hosts, grouped by identical output.
+fn grouped_output(theme: &Theme, run: &mut CommandRun) -> Vec<Line<'static>> {
+    let show_diff = run.show_diff;
+    let mut lines = vec![];
+    for (index, group) in run.groups().iter().enumerate() {
+        let hosts = &group.hosts;
+        lines.push(Line::styled(
+            format!("---------------- {} ({})", nodes::fold(hosts), hosts.len()),
+            Style::new().fg(theme.accent),
+        ));
+
+        match (show_diff, index, group.diff.as_ref()) {
+            (true, 0, _) => lines.push(Line::raw(" (reference output)")),
+            (true, _, Some(diff)) => {
+                lines.extend(diff.iter().map(|line| match line {
+                    DiffLine::Same(text) => Line::raw(format!(" {}", text)),
+                    DiffLine::Removed(text) => {
+                        Line::styled(format!("-{}", text), Style::new().fg(theme.critical))
+                    }
+                    DiffLine::Added(text) => {
+                        Line::styled(format!("+{}", text), Style::new().fg(theme.ok))
+                    }
+                }));
+            }
+            _ => lines.extend(group.output.lines().map(|line| Line::raw(line.to_string()))),
+        }
+    }
+
+    let codes: Vec<String> = run
+        .exit_codes()
+        .into_iter()
+        .map(|(code, hosts)| format!("{}: {}", code, nodes::fold(&hosts)))
+        .collect();
+    if !codes.is_empty() {
+        lines.push(Line::styled(
+            format!("exit codes: {}", codes.join("  ")),
+            Style::new().bold(),
+        ));
+    }
+
+    lines
+}
+
+/// Renders the result of every drift check: the hosts agreeing with the majority, then the hosts
+/// deviating from it along with their differences.
+fn drift_report(theme: &Theme, keymap: &Keymap, run: &mut DriftRun) -> Paragraph<'static> {
+    let mut lines = vec![];
+    for index in 0..run.checks.len() {
+        let name = run.checks[index].name.clone();
+        let report = run.report(index);
+        let pending = match report.pending {
+            0 => String::new(),
+            pending => format!(", {} pending", pending),
+        };
+        let (style, summary) = match (&report.majority, report.outliers.len()) {
+            (None, _) => (Style::new().fg(theme.muted), String::from("waiting")),
+            (Some(_), 0) => (Style::new().fg(theme.ok), String::from("consistent")),
+            (Some(_), 1) => (Style::new().fg(theme.critical), String::from("1 deviation")),
+            (Some(_), count) => (
+                Style::new().fg(theme.critical),
+                format!("{} deviations", count),
+            ),
+        };
+        lines.push(Line::styled(
+            format!("{}: {}{}", name, summary, pending),
+            style.bold(),
+        ));
+
+        let Some(majority) = report.majority.as_ref() else {
+            continue;
+        };
+        lines.push(Line::raw(format!(
+            "  = {} ({}): {}",
+            nodes::fold(&majority.hosts),
+            majority.hosts.len(),
+            drift_value(majority.result.as_deref())
+        )));
+
+        for group in report.outliers.iter() {
+            let hosts = &group.hosts;
+            let header = format!("  ! {} ({}): ", nodes::fold(hosts), hosts.len());
+            match group.diff.as_ref() {
+                // Only show the lines that differ between multi-line outputs
+                Some(diff) => {
+                    lines.push(Line::styled(header, Style::new().fg(theme.warn)));
+                    lines.extend(diff.iter().filter_map(|line| match line {
+                        DiffLine::Same(_) => None,
+                        DiffLine::Removed(text) => Some(Line::styled(
+                            format!("    -{}", text),
+                            Style::new().fg(theme.critical),
+                        )),
+                        DiffLine::Added(text) => Some(Line::styled(
+                            format!("    +{}", text),
+                            Style::new().fg(theme.ok),
+                        )),
+                    }));
+                }
+                None => lines.push(Line::styled(
+                    header + &drift_value(group.result.as_deref()),
+                    Style::new().fg(theme.warn),
+                )),
+            }
+        }
+    }
+
+    let title = format!(
+        " drift ({}/{} hosts done) {} ",
+        run.done(),
+        run.hosts(),
+        hints(
+            keymap,
+            &[
+                (&[KeyAction::Rerun], "rerun"),
+                (&[KeyAction::Quit], "close")
+            ]
+        )
+    );
+
+    Paragraph::new(lines)
+        .block(Block::default().borders(Borders::TOP).title(title))
+        .scroll((run.scroll, 0))
+}
+
+/// Result of a drift check on a single line, long outputs being summarized.
+fn drift_value(result: Result<&str, &Error>) -> String {
+    match result {
+        Ok(output) => match output.lines().count() {
+            0 => String::from("(empty)"),
+            1 => output.to_string(),
+            count => format!("{} lines", count),
+        },
+        Err(error) => error.to_string(),
+    }
+}
+
+/// Lists the rules firing on at least one host, with the hosts they fire on.
+fn alerts_panel(theme: &Theme, keymap: &Keymap, alerts: &Alerts) -> Paragraph<'static> {
+    let firing = alerts.firing_rules();
+    let mut lines: Vec<Line> = firing
+        .iter()
+        .map(|(rule, hosts, since)| {
+            let rule_text = match rule.name == rule.text {
+                true => String::new(),
+                false => format!(" ({})", rule.text),
+            };
+            Line::styled(
+                format!(
+                    "{}{}: {} ({}) for {}",
+                    rule.name,
+                    rule_text,
+                    nodes::fold(hosts),
+                    hosts.len(),
+                    format_duration(since.elapsed())
+                ),
+                Style::new().fg(theme.critical),
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::raw("No alert firing"));
+    }
+
+    let title = format!(
+        " alerts ({} firing / {} rules) {} ",
+        firing.len(),
+        alerts.rules.len(),
+        hints(keymap, &[(&[KeyAction::Alerts], "close")])
+    );
+    Paragraph::new(lines).block(Block::default().borders(Borders::TOP).title(title))
+}
+
+/// Renders the latest entries of the log fitting in `height`, or older ones when scrolled back.
+/// The hints are the keys of the log in the tab it is shown in.
+fn log_pane(theme: &Theme, history: &History, height: u16, hints: &str) -> Paragraph<'static> {
+    let entries: Vec<_> = history.entries().collect();
+    // One line is taken by the border
+    let visible = height.saturating_sub(1) as usize;
+    let end = entries.len().saturating_sub(history.scroll);
+    let start = end.saturating_sub(visible);
+
+    let lines: Vec<Line> = entries[start..end]
+        .iter()
+        .map(|entry| {
+            let style = match entry.level {
+                log::Level::Error => Style::new().fg(theme.critical),
+                log::Level::Warn => Style::new().fg(theme.warn),
+                log::Level::Info => Style::new(),
+                log::Level::Debug | log::Level::Trace => Style::new().fg(theme.muted),
+            };
+            Line::styled(
+                format!(
+                    "{} {:<5} {} {}",
+                    format_time(entry.time),
+                    entry.level,
+                    entry.host.as_deref().unwrap_or("-"),
+                    entry.message
+                ),
+                style,
+            )
+        })
+        .collect();
+
+    let filter = match history.hosts.as_ref() {
+        Some(hosts) => {
+            let hosts: Vec<&String> = hosts.iter().collect();
+            format!(" of {}", nodes::fold(&hosts))
+        }
+        None => String::new(),
+    };
+    let title = format!(" log{} ({}/{}) {} ", filter, end, entries.len(), hints);
+
+    Paragraph::new(lines).block(Block::default().borders(Borders::TOP).title(title))
+}
+
+/// Colour of a load average normalized by the processor count of the host.
+fn load_color(theme: &Theme, normalized: f64) -> Color {
+    if normalized >= NORMALIZED_LOAD_HIGH {
+        theme.critical
+    } else if normalized >= NORMALIZED_LOAD_WARN {
+        theme.warn
+    } else {
+        theme.ok
+    }
+}
+
+/// Colour of the hosts failing with errors of the class.
+///
+/// Errors needing a fix on the monitoring side are critical, and network trouble is told apart
+/// from the errors of the commands run on the hosts.
+fn error_color(theme: &Theme, class: ErrorClass) -> Color {
+    match class {
+        ErrorClass::Dns | ErrorClass::Auth | ErrorClass::HostKey | ErrorClass::Io => theme.critical,
+        ErrorClass::Tcp | ErrorClass::Timeout => theme.network_error,
+        ErrorClass::Channel => theme.channel_error,
+        ErrorClass::Exec | ErrorClass::Parse | ErrorClass::Exit => theme.remote_error,
+    }
+}
+
+/// Style of the label of the hosts an alert fires on. Without a background colour, the label is
+/// reversed to stand out anyway.
+fn alert_style(theme: &Theme) -> Style {
+    let style = Style::new().fg(theme.alert_fg).bold();
+    match theme.alert_bg {
+        Color::Reset => style.reversed(),
+        background => style.bg(background),
+    }
+}
+
+fn replay_status(keymap: &Keymap, replay: &Replay) -> Paragraph<'static> {
+    let state = if replay.paused { "paused" } else { "playing" };
+    let hints = hints(
+        keymap,
+        &[
+            (&[KeyAction::ReplayPause], "pause"),
+            (&[KeyAction::SeekBackward, KeyAction::SeekForward], "seek"),
+            (&[KeyAction::ReplayFaster, KeyAction::ReplaySlower], "speed"),
+        ],
+    );
+    Paragraph::new(format!(
+        "REPLAY {} / {} x{} [{}]  {}",
+        format_duration(replay.position),
+        format_duration(replay.duration),
+        replay.speed,
+        state,
+        hints,
+    ))
+    .style(Style::new().reversed())
+}
+
+/// Draws the latest values of the history as a line of bars, scaled to the largest one.
+fn sparkline(history: &VecDeque<Duration>) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let recent: Vec<&Duration> = history
+        .iter()
+        .skip(history.len().saturating_sub(SPARKLINE_WIDTH))
+        .collect();
+    let max = recent.iter().max().map_or(0.0, |max| max.as_secs_f64());
+    if max == 0.0 {
+        return String::new();
+    }
+
+    recent
+        .into_iter()
+        .map(|value| BARS[((value.as_secs_f64() / max) * (BARS.len() - 1) as f64).round() as usize])
+        .collect()
+}
+
+fn format_latency(latency: &Duration) -> String {
+    format!("{}ms", latency.as_millis())
+}
+
+/// Formats used and total memory given in kB, e.g. `12.3G/64.0G`.
+fn format_memory(total: u64, available: u64) -> String {
+    let gib = |kb: u64| kb as f64 / (1024.0 * 1024.0);
+    format!(
+        "{:.1}G/{:.1}G",
+        gib(total.saturating_sub(available)),
+        gib(total)
+    )
+}
+
+/// Formats the time of day of a timestamp, in UTC.
+fn format_time(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Formats a duration in days, hours, minutes and seconds, e.g. `12d 03:04:05`.
+fn format_uptime(duration: Duration) -> String {
+    let days = duration.as_secs() / 86400;
+    let rest = Duration::from_secs(duration.as_secs() % 86400);
+    format!("{}d {}", days, format_duration(rest))
+}
+
+/// Formats a time given in seconds since the epoch as a UTC date.
+fn format_date(timestamp: u64) -> String {
+    // Civil date from the number of days since the epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02} {} UTC",
+        year,
+        month,
+        day,
+        format_duration(Duration::from_secs(timestamp % 86400))
+    )
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_date(1700000000), "2023-11-14 22:13:20 UTC");
+        // Years divisible by 100 are only leap years when divisible by 400
+        assert_eq!(format_date(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_date(4107542399), "2100-02-28 23:59:59 UTC");
+        assert_eq!(format_date(4107542400), "2100-03-01 00:00:00 UTC");
+    }
+
+    #[test]
+    fn builds_hints_from_the_keymap() {
+        let keymap = Keymap::default();
+        let scroll: Hint = (
+            &[KeyAction::SelectNext, KeyAction::SelectPrevious],
+            "scroll",
+        );
+        let close: Hint = (&[KeyAction::Quit], "close");
+        assert_eq!(hints(&keymap, &[scroll, close]), "j/k: scroll  q: close");
 
-    frame.render_widget(load_table, frame.size())
+        let config = crate::config::KeymapConfig {
+            bindings: [(String::from("quit"), crate::config::Keys::Many(vec![]))].into(),
+            ..Default::default()
+        };
+        let keymap = Keymap::from_config(&config).unwrap();
+        assert_eq!(hints(&keymap, &[scroll, close]), "j/k: scroll");
+    }
 }